@@ -21,13 +21,7 @@ import { Toaster } from "@/components/ui/toaster";
 import { useToast } from "@/hooks/use-toast";
 import confetti from "canvas-confetti";
 
-import initSolve, {
-  solve as solveWasm,
-} from "@/rust/pkg/bottle_swap_solver";
-
-interface RustSolverInput {
-  bottles: number[][];
-}
+import { solveInWorker, solverReady } from "@/lib/solver-client";
 
 const BOTTLE_TILT_ANGLE_DEGREES = -75;
 
@@ -59,7 +53,7 @@ export default function Home() {
   useEffect(() => {
     async function loadWasm() {
       try {
-        await initSolve();
+        await solverReady();
         setWasmReady(true);
         console.log("Rust Wasm module initialized successfully.");
         toast({
@@ -362,12 +356,13 @@ export default function Home() {
         while (b.length < bottleHeight) b.unshift(0);
         return b.slice(0, bottleHeight);
       });
-      const solverInput: RustSolverInput = { bottles: validatedBottles };
-      const result: Movement[] = solveWasm(
-        bottleHeight,
-        numBottles,
-        solverInput,
-      );
+      const response = await solveInWorker({
+        levels: bottleHeight,
+        n_of_bottles: numBottles,
+        bottles: validatedBottles,
+      });
+      if (response.error) throw new Error(response.error);
+      const result: Movement[] = response.movements;
       if (result && result.length === 0) {
         toast({
           title: "No Solution Found",
@@ -0,0 +1,47 @@
+import type { SolveRequest, SolveResponse, SolverReady } from "@/lib/types";
+
+let worker: Worker | null = null;
+let nextId = 1;
+const pending = new Map<number, (response: SolveResponse) => void>();
+
+let markReady: (message: SolverReady) => void = () => {};
+const ready = new Promise<void>((resolve, reject) => {
+  markReady = (message) =>
+    message.ready ? resolve() : reject(new Error(message.error));
+});
+
+function getWorker(): Worker {
+  if (!worker) {
+    worker = new Worker(new URL("./solver.worker.ts", import.meta.url), {
+      type: "module",
+    });
+    worker.onmessage = (event: MessageEvent<SolveResponse | SolverReady>) => {
+      if ("ready" in event.data) {
+        markReady(event.data);
+        return;
+      }
+      const resolve = pending.get(event.data.id);
+      if (resolve) {
+        pending.delete(event.data.id);
+        resolve(event.data);
+      }
+    };
+  }
+  return worker;
+}
+
+// Resolves once the worker has loaded the solver, starting it if needed.
+export function solverReady(): Promise<void> {
+  getWorker();
+  return ready;
+}
+
+export function solveInWorker(
+  request: Omit<SolveRequest, "id">,
+): Promise<SolveResponse> {
+  const id = nextId++;
+  return new Promise((resolve) => {
+    pending.set(id, resolve);
+    getWorker().postMessage({ ...request, id });
+  });
+}
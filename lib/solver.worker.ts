@@ -0,0 +1,31 @@
+import initSolve, {
+  handle_solve_request as handleSolveRequest,
+} from "@/rust/pkg/bottle_swap_solver";
+import type { SolveRequest, SolveResponse, SolverReady } from "@/lib/types";
+
+const ready = initSolve();
+ready.then(
+  () => postReady({ ready: true }),
+  (err: any) =>
+    postReady({ ready: false, error: err?.message || String(err) }),
+);
+
+function postReady(message: SolverReady) {
+  self.postMessage(message);
+}
+
+self.onmessage = async (event: MessageEvent<SolveRequest>) => {
+  const request = event.data;
+  let response: SolveResponse;
+  try {
+    await ready;
+    response = handleSolveRequest(request);
+  } catch (err: any) {
+    response = {
+      id: request?.id ?? 0,
+      movements: [],
+      error: err?.message || String(err),
+    };
+  }
+  self.postMessage(response);
+};
@@ -29,3 +29,106 @@ export interface PourAnimation {
   pourPositionY?: number;
 }
 
+
+export interface SearchStats {
+  strategy: string;
+  solved: boolean;
+  solution_length: number;
+  nodes_expanded?: number;
+  elapsed_ms: number;
+}
+
+export type PourRule = "full" | "single" | "partial";
+
+// A locked bottle opens once some bottle is full of `color`, or after
+// `moves` more moves.
+export type Lock = "open" | { color: number } | { moves: number };
+
+// A layout gives the target bottles top-down, like `bottles`.
+export type GoalMode =
+  | "single-color"
+  | "full-bottles"
+  | { layout: number[][] };
+
+export interface Rules {
+  pour?: PourRule;
+  goal?: GoalMode;
+  // Challenge mode: solve in at most this many moves.
+  max_moves?: number;
+}
+
+// Legal moves the solver skips; every option defaults to true.
+export interface Pruning {
+  skip_relabeling_pours?: boolean;
+}
+
+export interface SolveRequest {
+  id: number;
+  levels: number;
+  n_of_bottles: number;
+  bottles: number[][];
+  capacities?: number[];
+  locks?: Lock[];
+  rules?: Rules;
+  pruning?: Pruning;
+  strategy?: string;
+}
+
+// `moves` is optimal when `exact`; without `moves`, `exact` means the
+// state is unsolvable.
+export interface Distance {
+  moves?: number;
+  lower_bound: number;
+  exact: boolean;
+}
+
+export interface MoveEvaluation {
+  movement: Movement;
+  distance: Distance;
+}
+
+// Extra bottles are appended after the puzzle's own bottles.
+export interface ExtraBottlesPlan {
+  extra_bottles: number;
+  movements: Movement[];
+}
+
+// A puzzle read from the text format by `parse_puzzle_text`.
+export interface TextPuzzle {
+  levels: number;
+  n_of_bottles: number;
+  game_state: GameState;
+}
+
+// A level of the catalog, as read by `load_level_file` and written by
+// `save_level_file`.
+export interface LevelFile {
+  version: number;
+  levels: number;
+  n_of_bottles: number;
+  bottles: number[][];
+  capacities?: number[];
+  locks?: Lock[];
+  rules?: Rules;
+  pruning?: Pruning;
+  par?: number;
+  solution?: Movement[];
+  author?: string;
+  tags?: string[];
+}
+
+export interface SolveResponse {
+  id: number;
+  movements: Movement[];
+  stats?: SearchStats;
+  error?: string;
+}
+
+// Posted once by the solver worker when its wasm module has loaded or failed.
+export interface SolverReady {
+  ready: boolean;
+  error?: string;
+}
+
+/** Color id of a cell the player has not revealed yet. */
+export const UNKNOWN_COLOR = 255;
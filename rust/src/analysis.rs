@@ -0,0 +1,56 @@
+use crate::{is_final_state_game, legal_pour_amounts, move_liquid_state, pour_amount, State};
+use std::collections::{HashSet, VecDeque};
+
+fn has_legal_move(state: &State) -> bool {
+    (0..state.n_of_bottles).any(|from| {
+        (0..state.n_of_bottles).any(|to| from != to && pour_amount(state, from, to) > 0)
+    })
+}
+
+/// Unsolved with no pour the solvers would make.
+pub(crate) fn is_dead_state(state: &State) -> bool {
+    !is_final_state_game(state) && !has_legal_move(state)
+}
+
+/// Searches every pour the player may make, pruned or not. `false` only means
+/// the state could not be proven dead within `max_states` reachable positions.
+pub(crate) fn is_dead_end(state: &State, max_states: usize) -> bool {
+    if is_final_state_game(state) {
+        return false;
+    }
+
+    let mut start = state.clone();
+    start.previous_state = None;
+
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert(start.clone());
+    queue.push_back(start);
+
+    while let Some(current) = queue.pop_front() {
+        for from in 0..current.n_of_bottles {
+            for to in 0..current.n_of_bottles {
+                if from == to {
+                    continue;
+                }
+
+                for amount in legal_pour_amounts(&current, from, to) {
+                    let mut next_state = move_liquid_state(&current, from, to, amount);
+                    if is_final_state_game(&next_state) {
+                        return false;
+                    }
+
+                    next_state.previous_state = None;
+                    if visited.insert(next_state.clone()) {
+                        if visited.len() > max_states {
+                            return false;
+                        }
+                        queue.push_back(next_state);
+                    }
+                }
+            }
+        }
+    }
+
+    true
+}
@@ -0,0 +1,88 @@
+use crate::optimizer::shorten_movements_until;
+use crate::{beam_search, extract_movements, is_final_state_game, now_ms, Movement, State};
+
+const INITIAL_BEAM_WIDTH: usize = 8;
+const MAX_BEAM_WIDTH: usize = 1 << 16;
+
+/// Doubles the beam width until `deadline` (in `now_ms` milliseconds),
+/// reporting each shorter solution to `on_improvement`.
+pub(crate) fn anytime_search(
+    initial_state: &State,
+    deadline: f64,
+    on_improvement: &mut dyn FnMut(&[Movement]),
+) -> Option<Vec<Movement>> {
+    let mut best: Option<Vec<Movement>> = None;
+    let mut beam_width = INITIAL_BEAM_WIDTH;
+    let mut nodes_expanded = 0;
+
+    while beam_width <= MAX_BEAM_WIDTH && now_ms() < deadline {
+        let solution = beam_search(
+            initial_state.clone(),
+            beam_width,
+            deadline,
+            &mut nodes_expanded,
+        )
+        .filter(is_final_state_game);
+
+        if let Some(solution) = solution {
+            let movements =
+                shorten_movements_until(initial_state, extract_movements(solution), deadline);
+            if best
+                .as_ref()
+                .is_none_or(|best| movements.len() < best.len())
+            {
+                on_improvement(&movements);
+                best = Some(movements);
+            }
+        }
+
+        beam_width *= 2;
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::verify_movements;
+    use crate::test_support::board;
+    use crate::Rules;
+
+    fn start() -> State {
+        board(
+            3,
+            &[&[2, 1, 3], &[1, 3, 1], &[2, 2, 3], &[0, 0, 0], &[0, 0, 0]],
+            Rules::default(),
+        )
+    }
+
+    #[test]
+    fn reports_only_shorter_solutions() {
+        let start = start();
+        let mut reported: Vec<Vec<Movement>> = Vec::new();
+        let best = anytime_search(&start, now_ms() + 5000.0, &mut |movements| {
+            reported.push(movements.to_vec())
+        });
+
+        assert!(!reported.is_empty());
+        assert!(reported
+            .windows(2)
+            .all(|pair| pair[1].len() < pair[0].len()));
+        assert!(reported
+            .iter()
+            .all(|movements| verify_movements(&start, movements)));
+        assert_eq!(best.as_ref(), reported.last());
+    }
+
+    #[test]
+    fn stops_at_the_deadline() {
+        let start = start();
+        let mut reported = 0;
+        let best = anytime_search(&start, now_ms() - 1.0, &mut |_| reported += 1);
+
+        assert_eq!(best, None);
+        assert_eq!(reported, 0);
+        assert_eq!(beam_search(start, 1000, now_ms() - 1.0, &mut 0), None);
+    }
+}
@@ -0,0 +1,317 @@
+use crate::analysis::is_dead_state;
+use crate::{is_final_state_game, move_liquid_state, pour_amounts, Bottle, GoalMode, State};
+use std::collections::HashMap;
+
+/// Bottle contents in sorted order: states with the same key are relabelings
+/// of each other.
+type CanonicalKey = Vec<Bottle>;
+
+struct Node {
+    state: State,
+    /// Forward pour and parent index; backward nodes pour into their parent.
+    link: Option<(usize, usize, usize, usize)>,
+}
+
+#[derive(Default)]
+struct Side {
+    nodes: Vec<Node>,
+    index: HashMap<CanonicalKey, usize>,
+}
+
+impl Side {
+    fn insert(
+        &mut self,
+        key: CanonicalKey,
+        state: State,
+        link: Option<(usize, usize, usize, usize)>,
+    ) -> usize {
+        self.index.insert(key, self.nodes.len());
+        self.nodes.push(Node { state, link });
+        self.nodes.len() - 1
+    }
+}
+
+fn canonical_key(state: &State) -> CanonicalKey {
+    let mut key = state.state[0..state.n_of_bottles].to_vec();
+    key.sort();
+    key
+}
+
+/// Only defined for equal capacities, no locks and a goal that does not fix
+/// which bottle holds what.
+fn canonical_goal(state: &State) -> Option<State> {
+    if !state.has_uniform_capacity()
+        || state.has_locks()
+        || matches!(state.rules().goal, GoalMode::Layout(_))
+    {
+        return None;
+    }
+
+    let mut units: HashMap<u8, usize> = HashMap::new();
+    for bottle in &state.state[0..state.n_of_bottles] {
+        for &color in &bottle.state[0..bottle.liquid_level] {
+            *units.entry(color).or_default() += 1;
+        }
+    }
+    let mut units: Vec<(u8, usize)> = units.into_iter().collect();
+    units.sort();
+
+    let mut goal = state.clone();
+    goal.previous_state = None;
+    goal.last_movement = None;
+    goal.how_much_moved = 0;
+
+    let mut index = 0;
+    for (color, mut count) in units {
+        while count > 0 {
+            if index >= state.n_of_bottles {
+                return None;
+            }
+            let level = count.min(state.levels);
+            let mut bottle = Bottle::empty(state.levels);
+            bottle.state[0..level].fill(color);
+            bottle.top_liquid = color;
+            bottle.liquid_level = level;
+            goal.state[index] = bottle;
+            count -= level;
+            index += 1;
+        }
+    }
+    for bottle in &mut goal.state[index..state.n_of_bottles] {
+        *bottle = Bottle::empty(state.levels);
+    }
+
+    Some(goal)
+}
+
+fn successors(state: &State) -> Vec<(State, usize, usize, usize)> {
+    let mut result = Vec::new();
+    for from in 0..state.n_of_bottles {
+        for to in 0..state.n_of_bottles {
+            if from == to {
+                continue;
+            }
+            for amount in pour_amounts(state, from, to) {
+                let mut next_state = move_liquid_state(state, from, to, amount);
+                next_state.previous_state = None;
+                result.push((next_state, from, to, amount));
+            }
+        }
+    }
+    result
+}
+
+/// Reverse pours, kept only if the rules allow the forward pour to move
+/// exactly that amount.
+fn predecessors(state: &State) -> Vec<(State, usize, usize, usize)> {
+    let mut result = Vec::new();
+
+    for to in 0..state.n_of_bottles {
+        let to_bottle = &state.state[to];
+        if to_bottle.liquid_level == 0 {
+            continue;
+        }
+
+        let run = (0..to_bottle.liquid_level)
+            .rev()
+            .take_while(|&k| to_bottle.state[k] == to_bottle.top_liquid)
+            .count();
+
+        for amount in 1..=run {
+            for from in 0..state.n_of_bottles {
+                if from == to
+                    || state.state[from].liquid_level + amount > state.state[from].capacity()
+                {
+                    continue;
+                }
+
+                let mut predecessor = move_liquid_state(state, to, from, amount);
+                predecessor.previous_state = None;
+                predecessor.last_movement = None;
+
+                if pour_amounts(&predecessor, from, to).any(|legal| legal == amount) {
+                    result.push((predecessor, from, to, amount));
+                }
+            }
+        }
+    }
+
+    result
+}
+
+fn relabeling(backward: &State, forward: &State) -> Vec<usize> {
+    let mut used = vec![false; forward.n_of_bottles];
+    (0..backward.n_of_bottles)
+        .map(|i| {
+            let j = (0..forward.n_of_bottles)
+                .find(|&j| !used[j] && forward.state[j] == backward.state[i])
+                .unwrap();
+            used[j] = true;
+            j
+        })
+        .collect()
+}
+
+/// Replays the forward pours to rebuild the `previous_state` chain.
+fn forward_path(initial_state: &State, nodes: &[Node], mut index: usize) -> State {
+    let mut pours = Vec::new();
+    while let Some((from, to, amount, parent)) = nodes[index].link {
+        pours.push((from, to, amount));
+        index = parent;
+    }
+
+    let mut current = initial_state.clone();
+    for (from, to, amount) in pours.into_iter().rev() {
+        current = move_liquid_state(&current, from, to, amount);
+    }
+    current
+}
+
+fn join(meeting: &State, nodes: &[Node], mut index: usize) -> Option<State> {
+    let mapping = relabeling(&nodes[index].state, meeting);
+
+    let mut current = meeting.clone();
+    while let Some((from, to, amount, parent)) = nodes[index].link {
+        let (from, to) = (mapping[from], mapping[to]);
+        if !pour_amounts(&current, from, to).any(|legal| legal == amount) {
+            return None;
+        }
+        current = move_liquid_state(&current, from, to, amount);
+        index = parent;
+    }
+
+    is_final_state_game(&current).then_some(current)
+}
+
+/// Only the canonical goal seeds the backward side; other solved layouts are
+/// only found going forward, so the solution is not always the shortest.
+pub(crate) fn bidirectional_search(
+    initial_state: State,
+    max_states: usize,
+    nodes_expanded: &mut usize,
+) -> Option<State> {
+    if is_final_state_game(&initial_state) {
+        return Some(initial_state);
+    }
+
+    let goal = canonical_goal(&initial_state)?;
+
+    let mut start = initial_state.clone();
+    start.previous_state = None;
+    let start_key = canonical_key(&start);
+
+    let mut forward = Side::default();
+    let mut backward = Side::default();
+    forward.insert(start_key.clone(), start, None);
+    backward.insert(canonical_key(&goal), goal, None);
+
+    if let Some(&meeting) = backward.index.get(&start_key) {
+        return join(&initial_state, &backward.nodes, meeting);
+    }
+
+    let mut forward_frontier = vec![0];
+    let mut backward_frontier = vec![0];
+
+    while !forward_frontier.is_empty() && !backward_frontier.is_empty() {
+        if forward_frontier.len() <= backward_frontier.len() {
+            let mut next_frontier = Vec::new();
+            for index in forward_frontier {
+                *nodes_expanded += 1;
+                let current = forward.nodes[index].state.clone();
+                for (next_state, from, to, amount) in successors(&current) {
+                    let next_key = canonical_key(&next_state);
+                    if is_final_state_game(&next_state) {
+                        let path = forward_path(&initial_state, &forward.nodes, index);
+                        return Some(move_liquid_state(&path, from, to, amount));
+                    }
+                    if let Some(&meeting) = backward.index.get(&next_key) {
+                        let path = forward_path(&initial_state, &forward.nodes, index);
+                        let path = move_liquid_state(&path, from, to, amount);
+                        if let Some(solution) = join(&path, &backward.nodes, meeting) {
+                            return Some(solution);
+                        }
+                    }
+                    if is_dead_state(&next_state) || forward.index.contains_key(&next_key) {
+                        continue;
+                    }
+                    if forward.nodes.len() + backward.nodes.len() >= max_states {
+                        return None;
+                    }
+                    next_frontier.push(forward.insert(
+                        next_key,
+                        next_state,
+                        Some((from, to, amount, index)),
+                    ));
+                }
+            }
+            forward_frontier = next_frontier;
+        } else {
+            let mut next_frontier = Vec::new();
+            for index in backward_frontier {
+                *nodes_expanded += 1;
+                let current = backward.nodes[index].state.clone();
+                for (previous_state, from, to, amount) in predecessors(&current) {
+                    let previous_key = canonical_key(&previous_state);
+                    if backward.index.contains_key(&previous_key) {
+                        continue;
+                    }
+                    if forward.nodes.len() + backward.nodes.len() >= max_states {
+                        return None;
+                    }
+                    let previous_index = backward.insert(
+                        previous_key.clone(),
+                        previous_state,
+                        Some((from, to, amount, index)),
+                    );
+                    if let Some(&meeting) = forward.index.get(&previous_key) {
+                        let path = forward_path(&initial_state, &forward.nodes, meeting);
+                        if let Some(solution) = join(&path, &backward.nodes, previous_index) {
+                            return Some(solution);
+                        }
+                    }
+                    next_frontier.push(previous_index);
+                }
+            }
+            backward_frontier = next_frontier;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::verify_movements;
+    use crate::test_support::{random_boards, solved_boards};
+    use crate::{extract_movements, Rules};
+
+    #[test]
+    fn finds_valid_solutions_no_shorter_than_optimal() {
+        for goal in [GoalMode::SingleColor, GoalMode::FullBottles] {
+            let rules = Rules {
+                goal,
+                ..Rules::default()
+            };
+            for (start, distances) in solved_boards(31, 10, &rules) {
+                let optimum = distances.get(&start.state).copied();
+                let solution = bidirectional_search(start.clone(), 100000, &mut 0);
+                assert_eq!(solution.is_some(), optimum.is_some(), "{:?}", start.state);
+
+                if let Some(solution) = solution {
+                    let movements = extract_movements(solution);
+                    assert!(verify_movements(&start, &movements));
+                    assert!(movements.len() >= optimum.unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stops_at_the_state_budget() {
+        let start = random_boards(37, 1, &Rules::default()).remove(0);
+        let mut nodes_expanded = 0;
+        assert!(bidirectional_search(start, 3, &mut nodes_expanded).is_none());
+        assert!(nodes_expanded <= 3);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::heuristics::Heuristic;
+use crate::{ida_star_search_recursive, State};
+use std::collections::{HashMap, HashSet};
+
+/// With an admissible `heuristic`, `None` proves there is no solution within
+/// `max_moves`.
+pub(crate) fn bounded_search(
+    initial_state: State,
+    max_moves: usize,
+    heuristic: &dyn Heuristic,
+    nodes_expanded: &mut usize,
+) -> Option<State> {
+    let mut bound = heuristic.estimate(&initial_state);
+
+    while bound <= max_moves as f64 {
+        let mut path = vec![initial_state.clone()];
+        let mut visited = HashSet::new();
+        visited.insert(initial_state.clone());
+
+        let result = ida_star_search_recursive(
+            &mut path,
+            &mut visited,
+            0.0,
+            bound,
+            &mut HashMap::new(),
+            heuristic,
+            nodes_expanded,
+        );
+
+        if result.is_goal {
+            return path.pop();
+        }
+        if result.cost == f64::INFINITY {
+            return None;
+        }
+        bound = result.cost;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::optimizer::verify_movements;
+    use crate::test_support::board;
+    use crate::{solve_state, PourRule, Rules};
+
+    #[test]
+    fn solve_state_falls_back_to_a_bounded_search_within_the_limit() {
+        let rules = Rules {
+            pour: PourRule::Single,
+            ..Rules::default()
+        };
+        let start = board(3, &[&[2, 1, 3], &[1, 3, 1], &[2, 2, 3], &[0, 0, 0]], rules);
+
+        let mut below = start.clone();
+        below.rules_mut().max_moves = Some(6);
+        assert_eq!(
+            solve_state(&below).err(),
+            Some("No solution within 6 moves".to_string())
+        );
+
+        let mut within = start.clone();
+        within.rules_mut().max_moves = Some(7);
+        let outcome = solve_state(&within).unwrap();
+        assert_eq!(outcome.movements.len(), 7);
+        assert!(verify_movements(&start, &outcome.movements));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::weighted_astar::{astar_search, AStarOutcome};
+use crate::{
+    admissible_heuristic, extract_movements, is_final_state_game, legal_pour_amounts,
+    move_liquid_state, solve_state, Movement, State, DISTANCE_MAX_EXPANSIONS,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Distance {
+    pub moves: Option<usize>,
+    /// No solution is shorter than this.
+    pub lower_bound: usize,
+    /// With `moves` of `None`, the state is proven unsolvable.
+    pub exact: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MoveEvaluation {
+    pub movement: Movement,
+    pub distance: Distance,
+}
+
+/// Ignores any move limit.
+pub(crate) fn distance_to_goal(state: &State) -> Distance {
+    let mut start = state.clone();
+    start.previous_state = None;
+    start.rules_mut().max_moves = None;
+
+    bounded_distance(&start, DISTANCE_MAX_EXPANSIONS, &mut 0).unwrap_or_else(|lower_bound| {
+        let moves = solve_state(&start)
+            .ok()
+            .map(|outcome| outcome.movements.len());
+        Distance {
+            moves,
+            lower_bound,
+            exact: moves == Some(lower_bound),
+        }
+    })
+}
+
+/// The lower bound A* proved when the budget runs out.
+fn bounded_distance(
+    state: &State,
+    max_expansions: usize,
+    nodes_expanded: &mut usize,
+) -> Result<Distance, usize> {
+    if is_final_state_game(state) {
+        return Ok(Distance {
+            moves: Some(0),
+            lower_bound: 0,
+            exact: true,
+        });
+    }
+
+    match astar_search(
+        state.clone(),
+        &admissible_heuristic(),
+        1.0,
+        max_expansions,
+        nodes_expanded,
+    ) {
+        AStarOutcome::Solved(solution) => {
+            let moves = extract_movements(*solution).len();
+            Ok(Distance {
+                moves: Some(moves),
+                lower_bound: moves,
+                exact: true,
+            })
+        }
+        AStarOutcome::Exhausted => Ok(Distance {
+            moves: None,
+            lower_bound: 0,
+            exact: true,
+        }),
+        AStarOutcome::OutOfBudget { lower_bound } => Err(lower_bound.ceil() as usize),
+    }
+}
+
+/// Includes the moves the solvers prune.
+pub(crate) fn evaluate_moves(state: &State) -> Vec<MoveEvaluation> {
+    let mut start = state.clone();
+    start.previous_state = None;
+    start.rules_mut().max_moves = None;
+
+    let mut moves = Vec::new();
+    for from in 0..start.n_of_bottles {
+        for to in 0..start.n_of_bottles {
+            if from != to {
+                moves.extend(legal_pour_amounts(&start, from, to).map(|amount| Movement {
+                    from,
+                    to,
+                    amount,
+                }));
+            }
+        }
+    }
+
+    let mut nodes_expanded = 0;
+    let n_of_moves = moves.len();
+    moves
+        .into_iter()
+        .enumerate()
+        .map(|(i, movement)| {
+            // Whatever the earlier moves left unused goes to the later ones.
+            let remaining = DISTANCE_MAX_EXPANSIONS.saturating_sub(nodes_expanded);
+            let budget = remaining / (n_of_moves - i);
+
+            let mut next_state =
+                move_liquid_state(&start, movement.from, movement.to, movement.amount);
+            next_state.previous_state = None;
+            let distance = bounded_distance(&next_state, budget, &mut nodes_expanded)
+                .unwrap_or_else(|lower_bound| Distance {
+                    moves: None,
+                    lower_bound,
+                    exact: false,
+                });
+            MoveEvaluation { movement, distance }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::solved_boards;
+    use crate::Rules;
+
+    #[test]
+    fn distances_match_breadth_first_search() {
+        for (start, exact) in solved_boards(43, 15, &Rules::default()) {
+            let distance = distance_to_goal(&start);
+            assert!(distance.exact);
+            assert_eq!(distance.moves, exact.get(&start.state).copied());
+
+            for evaluation in evaluate_moves(&start) {
+                let Movement { from, to, amount } = evaluation.movement;
+                let next_state = move_liquid_state(&start, from, to, amount);
+                let optimum = exact.get(&next_state.state).copied();
+                let distance = evaluation.distance;
+
+                assert!(optimum.is_none_or(|optimum| distance.lower_bound <= optimum));
+                if distance.exact {
+                    assert_eq!(distance.moves, optimum);
+                }
+            }
+        }
+    }
+}
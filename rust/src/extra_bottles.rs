@@ -0,0 +1,118 @@
+use crate::analysis::is_dead_end;
+use crate::{
+    convert_to_internal_state, solve_state, GoalMode, JsGameState, Lock, Movement,
+    DEAD_END_SEARCH_LIMIT, MAX_N_OF_BOTTLES,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// The extra bottles are numbered from the puzzle's `n_of_bottles`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExtraBottlesPlan {
+    pub extra_bottles: usize,
+    pub movements: Vec<Movement>,
+}
+
+fn with_extra_bottles(game_state: &JsGameState, levels: usize, extra: usize) -> JsGameState {
+    let mut game_state = game_state.clone();
+    game_state
+        .bottles
+        .extend(std::iter::repeat_n(vec![0; levels], extra));
+    if let Some(capacities) = &mut game_state.capacities {
+        capacities.extend(std::iter::repeat_n(levels, extra));
+    }
+    if let Some(locks) = &mut game_state.locks {
+        locks.extend(std::iter::repeat_n(Lock::Open, extra));
+    }
+    if let GoalMode::Layout(layout) = &mut game_state.rules.goal {
+        let mut extended = layout.as_ref().clone();
+        extended.extend(std::iter::repeat_n(vec![0; levels], extra));
+        *layout = Arc::new(extended);
+    }
+    game_state
+}
+
+/// A board the solvers give up on counts as unsolvable.
+pub(crate) fn plan_extra_bottles(
+    game_state: &JsGameState,
+    levels: usize,
+    n_of_bottles: usize,
+    max_extra: usize,
+) -> Result<ExtraBottlesPlan, String> {
+    // Validates the puzzle as given before any bottle is added.
+    convert_to_internal_state(game_state, levels, n_of_bottles)?;
+
+    let max_extra = max_extra.min(MAX_N_OF_BOTTLES - n_of_bottles);
+    for extra in 0..=max_extra {
+        let state = convert_to_internal_state(
+            &with_extra_bottles(game_state, levels, extra),
+            levels,
+            n_of_bottles + extra,
+        )?;
+        if is_dead_end(&state, DEAD_END_SEARCH_LIMIT) {
+            continue;
+        }
+
+        if let Ok(outcome) = solve_state(&state) {
+            return Ok(ExtraBottlesPlan {
+                extra_bottles: extra,
+                movements: outcome.movements,
+            });
+        }
+    }
+
+    Err(format!(
+        "No solution found with up to {} extra bottles",
+        max_extra
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::verify_movements;
+    use crate::{Pruning, Rules};
+
+    fn game_state(bottles: Vec<Vec<u8>>) -> JsGameState {
+        JsGameState {
+            bottles,
+            capacities: None,
+            locks: None,
+            rules: Rules::default(),
+            pruning: Pruning::default(),
+        }
+    }
+
+    #[test]
+    fn adds_the_fewest_bottles_that_make_the_puzzle_solvable() {
+        let solvable = game_state(vec![vec![2, 1], vec![1, 2], vec![0, 0]]);
+        let plan = plan_extra_bottles(&solvable, 2, 3, 2).unwrap();
+        assert_eq!(plan.extra_bottles, 0);
+
+        let stuck = game_state(vec![vec![2, 1], vec![1, 2]]);
+        let plan = plan_extra_bottles(&stuck, 2, 2, 2).unwrap();
+        assert_eq!(plan.extra_bottles, 1);
+        let extended = convert_to_internal_state(&with_extra_bottles(&stuck, 2, 1), 2, 3).unwrap();
+        assert!(verify_movements(&extended, &plan.movements));
+
+        assert_eq!(
+            plan_extra_bottles(&stuck, 2, 2, 0).unwrap_err(),
+            "No solution found with up to 0 extra bottles"
+        );
+    }
+
+    #[test]
+    fn reports_the_limit_clamped_to_the_most_bottles() {
+        // Pairs of full bottles with two colors swapped, and no room to pour.
+        let pairs = MAX_N_OF_BOTTLES as u8 / 2;
+        let full = game_state(
+            (1..=pairs)
+                .flat_map(|color| [vec![color + pairs, color], vec![color, color + pairs]])
+                .collect(),
+        );
+        assert_eq!(
+            plan_extra_bottles(&full, 2, MAX_N_OF_BOTTLES, 5).unwrap_err(),
+            "No solution found with up to 0 extra bottles"
+        );
+    }
+}
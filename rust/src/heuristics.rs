@@ -0,0 +1,418 @@
+use crate::{is_final_state_bottle, is_final_state_game, GoalMode, State, UNKNOWN_COLOR};
+use std::collections::{HashMap, HashSet};
+
+pub(crate) trait Heuristic: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Whether `estimate` never overestimates the number of remaining moves.
+    fn is_admissible(&self) -> bool;
+
+    fn estimate(&self, state: &State) -> f64;
+}
+
+/// Well informed but not admissible, so only for the greedy searches.
+pub(crate) struct Greedy;
+
+impl Heuristic for Greedy {
+    fn name(&self) -> &'static str {
+        "greedy"
+    }
+
+    fn is_admissible(&self) -> bool {
+        false
+    }
+
+    fn estimate(&self, state: &State) -> f64 {
+        calculate_heuristic(state)
+    }
+}
+
+/// Every segment resting on another color has to be poured out at least once.
+/// 0 under a goal layout with mixed bottles, where segments may stay buried.
+pub(crate) struct BuriedSegments;
+
+impl Heuristic for BuriedSegments {
+    fn name(&self) -> &'static str {
+        "buried-segments"
+    }
+
+    fn is_admissible(&self) -> bool {
+        true
+    }
+
+    fn estimate(&self, state: &State) -> f64 {
+        if has_mixed_layout(state) {
+            return 0.0;
+        }
+
+        state.state[0..state.n_of_bottles]
+            .iter()
+            .map(|bottle| {
+                count_segments(bottle.state[0..bottle.liquid_level].iter()).saturating_sub(1)
+            })
+            .sum::<usize>() as f64
+    }
+}
+
+/// A pour merges at most one segment, so each segment of a color beyond the
+/// bottles it can end up in costs a move. 0 under a goal layout with mixed
+/// bottles, which may keep a color in pieces.
+pub(crate) struct ColorFragmentation;
+
+impl Heuristic for ColorFragmentation {
+    fn name(&self) -> &'static str {
+        "color-fragmentation"
+    }
+
+    fn is_admissible(&self) -> bool {
+        true
+    }
+
+    fn estimate(&self, state: &State) -> f64 {
+        if has_mixed_layout(state) {
+            return 0.0;
+        }
+
+        let mut segments: HashMap<u8, usize> = HashMap::new();
+        let mut units: HashMap<u8, usize> = HashMap::new();
+
+        for bottle in &state.state[0..state.n_of_bottles] {
+            for level in 0..bottle.liquid_level {
+                let color = bottle.state[level];
+                *units.entry(color).or_default() += 1;
+                if level == 0 || bottle.state[level - 1] != color {
+                    *segments.entry(color).or_default() += 1;
+                }
+            }
+        }
+
+        let n_of_colors = units.len();
+        let bottles_per_color = (state.n_of_bottles + 1).saturating_sub(n_of_colors);
+
+        segments
+            .iter()
+            .map(|(color, &count)| count.saturating_sub(bottles_per_color.min(units[color])))
+            .sum::<usize>() as f64
+    }
+}
+
+/// Under a goal layout, every bottle whose liquid is not the bottom of its
+/// target has to be poured from at least once, and each move pours from one.
+pub(crate) struct MisplacedBottles;
+
+impl Heuristic for MisplacedBottles {
+    fn name(&self) -> &'static str {
+        "misplaced-bottles"
+    }
+
+    fn is_admissible(&self) -> bool {
+        true
+    }
+
+    fn estimate(&self, state: &State) -> f64 {
+        let GoalMode::Layout(layout) = &state.rules().goal else {
+            return 0.0;
+        };
+
+        state.state[0..state.n_of_bottles]
+            .iter()
+            .zip(layout.iter())
+            .filter(|(bottle, target)| {
+                let mut target_liquid = target.iter().rev().take_while(|&&color| color != 0);
+                !bottle.state[0..bottle.liquid_level].iter().all(|&color| {
+                    target_liquid
+                        .next()
+                        .is_some_and(|&target| color == target || color == UNKNOWN_COLOR)
+                })
+            })
+            .count() as f64
+    }
+}
+
+/// The larger of the admissible bounds above; still admissible.
+pub(crate) struct LowerBound;
+
+impl Heuristic for LowerBound {
+    fn name(&self) -> &'static str {
+        "lower-bound"
+    }
+
+    fn is_admissible(&self) -> bool {
+        true
+    }
+
+    fn estimate(&self, state: &State) -> f64 {
+        if is_final_state_game(state) {
+            return 0.0;
+        }
+
+        BuriedSegments
+            .estimate(state)
+            .max(ColorFragmentation.estimate(state))
+            .max(MisplacedBottles.estimate(state))
+    }
+}
+
+pub(crate) fn heuristic_by_name(name: &str) -> Option<Box<dyn Heuristic>> {
+    match name {
+        "greedy" => Some(Box::new(Greedy)),
+        "buried-segments" => Some(Box::new(BuriedSegments)),
+        "color-fragmentation" => Some(Box::new(ColorFragmentation)),
+        "misplaced-bottles" => Some(Box::new(MisplacedBottles)),
+        "lower-bound" => Some(Box::new(LowerBound)),
+        _ => None,
+    }
+}
+
+fn has_mixed_layout(state: &State) -> bool {
+    let GoalMode::Layout(layout) = &state.rules().goal else {
+        return false;
+    };
+    layout.iter().any(|target| {
+        let mut colors = target.iter().filter(|&&color| color != 0);
+        colors
+            .next()
+            .is_some_and(|first| colors.any(|color| color != first))
+    })
+}
+
+fn count_segments<'a>(colors: impl Iterator<Item = &'a u8>) -> usize {
+    let mut segments = 0;
+    let mut previous = None;
+    for &color in colors {
+        if previous != Some(color) {
+            segments += 1;
+            previous = Some(color);
+        }
+    }
+    segments
+}
+
+pub(crate) fn calculate_heuristic(state: &State) -> f64 {
+    let mut score = 0.0;
+
+    let unfinished_bottles = state.state[0..state.n_of_bottles]
+        .iter()
+        .filter(|b| !is_final_state_bottle(b))
+        .count();
+    score += unfinished_bottles as f64;
+
+    let mut color_counts: HashMap<u8, Vec<(usize, usize)>> = HashMap::new();
+
+    for i in 0..state.n_of_bottles {
+        let bottle = &state.state[i];
+
+        if bottle.liquid_level == 0 {
+            continue;
+        }
+
+        for level in 0..bottle.liquid_level {
+            let color = bottle.state[level];
+            if color > 0 {
+                color_counts.entry(color).or_default().push((i, level));
+            }
+        }
+    }
+
+    for (color, locations) in color_counts {
+        if color > 0 {
+            let mut bottles_with_this_color = HashSet::new();
+            for (bottle_idx, _) in &locations {
+                bottles_with_this_color.insert(bottle_idx);
+            }
+
+            if bottles_with_this_color.len() > 1 {
+                score += (bottles_with_this_color.len() - 1) as f64 * 0.8;
+            }
+
+            let total_units = locations.len();
+            let max_units_per_bottle = state.state[0..state.n_of_bottles]
+                .iter()
+                .map(|bottle| bottle.capacity())
+                .max()
+                .unwrap_or(0);
+
+            if total_units > 0 && total_units <= max_units_per_bottle {
+                score += (bottles_with_this_color.len() - 1) as f64 * 0.5;
+            }
+        }
+    }
+
+    for i in 0..state.n_of_bottles {
+        let bottle = &state.state[i];
+        if bottle.liquid_level <= 1 {
+            continue;
+        }
+
+        let mut transitions = 0;
+        for j in 1..bottle.liquid_level {
+            if bottle.state[j] != bottle.state[j - 1] {
+                transitions += 1;
+            }
+        }
+
+        score += transitions as f64 * 0.3;
+    }
+
+    for i in 0..state.n_of_bottles {
+        let bottle = &state.state[i];
+        if bottle.liquid_level == 0 || bottle.liquid_level == bottle.capacity() {
+            continue;
+        }
+
+        let mut is_single_color = true;
+        let first_color = bottle.state[0];
+
+        for j in 1..bottle.liquid_level {
+            if bottle.state[j] != first_color {
+                is_single_color = false;
+                break;
+            }
+        }
+
+        if is_single_color {
+            score -= 0.3;
+        }
+    }
+
+    if state.n_of_bottles >= 8 && state.levels >= 7 {
+        let partially_filled_bottles = state.state[0..state.n_of_bottles]
+            .iter()
+            .filter(|b| b.liquid_level > 0 && b.liquid_level < b.capacity())
+            .count();
+
+        score += partially_filled_bottles as f64 * 0.2;
+
+        for i in 0..state.n_of_bottles {
+            let bottle = &state.state[i];
+            if bottle.liquid_level > 0 && bottle.liquid_level < bottle.capacity() {
+                let all_same = (1..bottle.liquid_level).all(|j| bottle.state[j] == bottle.state[0]);
+
+                if all_same {
+                    score -= 0.5;
+                }
+            }
+        }
+    }
+
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{exact_distances, random_boards_per_pour_rule, solved_boards};
+    use crate::{legal_pour_amounts, move_liquid_state, GoalMode, Rules};
+    use std::sync::Arc;
+
+    #[test]
+    fn admissible_heuristics_never_overestimate() {
+        let heuristics: Vec<Box<dyn Heuristic>> =
+            ["buried-segments", "color-fragmentation", "lower-bound"]
+                .into_iter()
+                .filter_map(heuristic_by_name)
+                .collect();
+        assert!(heuristics.iter().all(|heuristic| heuristic.is_admissible()));
+
+        for goal in [GoalMode::SingleColor, GoalMode::FullBottles] {
+            let rules = Rules {
+                goal,
+                ..Rules::default()
+            };
+            for (start, distances) in solved_boards(7, 8, &rules) {
+                for (bottles, distance) in distances {
+                    let mut state = start.clone();
+                    state.state = bottles;
+                    for heuristic in &heuristics {
+                        assert!(
+                            heuristic.estimate(&state) <= distance as f64,
+                            "{} overestimates {:?} under {:?}",
+                            heuristic.name(),
+                            state.state,
+                            state.rules()
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn admissible_heuristics_never_overestimate_layout_goals() {
+        let heuristics: Vec<Box<dyn Heuristic>> = [
+            "buried-segments",
+            "color-fragmentation",
+            "misplaced-bottles",
+            "lower-bound",
+        ]
+        .into_iter()
+        .filter_map(heuristic_by_name)
+        .collect();
+
+        for start in random_boards_per_pour_rule(5, 8, &Rules::default()) {
+            // Colors fill the last bottles; the final unit goes on its own
+            // when there is room, splitting its color.
+            let mut sorted = vec![vec![0; start.levels]; start.n_of_bottles];
+            let mut units: Vec<u8> = start.state[0..start.n_of_bottles]
+                .iter()
+                .flat_map(|bottle| bottle.state[0..bottle.liquid_level].to_vec())
+                .collect();
+            units.sort_unstable_by(|a, b| b.cmp(a));
+            let mut target = start.n_of_bottles - 1;
+            let mut filled = 0;
+            for (i, &color) in units.iter().enumerate() {
+                let split = i + 1 == units.len() && target > 0;
+                if filled == start.levels || (i > 0 && units[i - 1] != color) || split {
+                    target -= 1;
+                    filled = 0;
+                }
+                sorted[target][start.levels - 1 - filled] = color;
+                filled += 1;
+            }
+
+            // A few pours in, bottles still mix colors.
+            let mut mixed = start.clone();
+            for _ in 0..3 {
+                let pour = (0..mixed.n_of_bottles)
+                    .flat_map(|from| (0..mixed.n_of_bottles).map(move |to| (from, to)))
+                    .filter(|(from, to)| from != to)
+                    .find_map(|(from, to)| {
+                        legal_pour_amounts(&mixed, from, to)
+                            .next()
+                            .map(|amount| (from, to, amount))
+                    });
+                if let Some((from, to, amount)) = pour {
+                    mixed = move_liquid_state(&mixed, from, to, amount);
+                }
+            }
+            let mixed: Vec<Vec<u8>> = mixed.state[0..mixed.n_of_bottles]
+                .iter()
+                .map(|bottle| {
+                    let mut target = bottle.state[0..bottle.liquid_level].to_vec();
+                    target.resize(start.levels, 0);
+                    target.reverse();
+                    target
+                })
+                .collect();
+
+            for layout in [sorted, mixed] {
+                let mut start = start.clone();
+                start.rules_mut().goal = GoalMode::Layout(Arc::new(layout));
+
+                for (bottles, distance) in exact_distances(&start) {
+                    let mut state = start.clone();
+                    state.state = bottles;
+                    for heuristic in &heuristics {
+                        assert!(
+                            heuristic.estimate(&state) <= distance as f64,
+                            "{} overestimates {:?} towards {:?}",
+                            heuristic.name(),
+                            state.state,
+                            state.rules().goal
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
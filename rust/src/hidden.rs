@@ -0,0 +1,161 @@
+use crate::mcts::Rng;
+use crate::{
+    extract_movements, pour_amounts, release_locks, run_strategy, Movement, State, Strategy,
+    UNKNOWN_COLOR,
+};
+use std::collections::HashMap;
+
+const HIDDEN_PLAN_BEAM_WIDTH: usize = 200;
+
+/// Assumes every color fills exactly `levels` units.
+fn hidden_units(state: &State) -> Result<Vec<u8>, String> {
+    let mut counts: HashMap<u8, usize> = HashMap::new();
+    let mut unknown = 0;
+    for bottle in &state.state[0..state.n_of_bottles] {
+        for &color in &bottle.state[0..bottle.liquid_level] {
+            if color == UNKNOWN_COLOR {
+                unknown += 1;
+            } else {
+                *counts.entry(color).or_default() += 1;
+            }
+        }
+    }
+
+    let mut units = Vec::with_capacity(unknown);
+    let mut colors: Vec<(u8, usize)> = counts.iter().map(|(&c, &n)| (c, n)).collect();
+    colors.sort();
+    for (color, count) in colors {
+        if count > state.levels {
+            return Err(format!(
+                "Color {} has {} units, more than the {} levels",
+                color, count, state.levels
+            ));
+        }
+        units.extend(std::iter::repeat_n(color, state.levels - count));
+    }
+
+    if units.len() > unknown || (unknown - units.len()) % state.levels != 0 {
+        return Err(format!(
+            "{} hidden cells cannot complete every color to {} units",
+            unknown, state.levels
+        ));
+    }
+
+    let mut fresh_colors = (1..UNKNOWN_COLOR).filter(|color| !counts.contains_key(color));
+    while units.len() < unknown {
+        let color = fresh_colors
+            .next()
+            .ok_or_else(|| "Too many colors on the board".to_string())?;
+        units.extend(std::iter::repeat_n(color, state.levels));
+    }
+
+    Ok(units)
+}
+
+fn complete(state: &State, units: &[u8], rng: &mut Rng) -> State {
+    let mut units = units.to_vec();
+    for i in (1..units.len()).rev() {
+        units.swap(i, (rng.next_u64() % (i as u64 + 1)) as usize);
+    }
+
+    let mut completion = state.clone();
+    completion.previous_state = None;
+    let mut units = units.into_iter();
+    for bottle in &mut completion.state[0..state.n_of_bottles] {
+        for cell in &mut bottle.state[0..bottle.liquid_level] {
+            if *cell == UNKNOWN_COLOR {
+                *cell = units.next().unwrap();
+            }
+        }
+        if bottle.liquid_level > 0 {
+            bottle.top_liquid = bottle.state[bottle.liquid_level - 1];
+        }
+    }
+    release_locks(&mut completion);
+    completion
+}
+
+/// Votes on the first moves of solved random completions. Only pours legal
+/// on the visible board count.
+pub(crate) fn plan_hidden_move(
+    state: &State,
+    samples: usize,
+    seed: u64,
+) -> Result<Option<Movement>, String> {
+    let units = hidden_units(state)?;
+    let samples = if units.is_empty() { 1 } else { samples.max(1) };
+
+    let mut rng = Rng::new(seed);
+    let mut votes: Vec<(Movement, usize)> = Vec::new();
+
+    for _ in 0..samples {
+        let completion = complete(state, &units, &mut rng);
+        let (solution, _) = run_strategy(Strategy::Beam(HIDDEN_PLAN_BEAM_WIDTH), completion);
+        let Some(first) =
+            solution.and_then(|solution| extract_movements(solution).into_iter().next())
+        else {
+            continue;
+        };
+
+        if !pour_amounts(state, first.from, first.to).any(|amount| amount == first.amount) {
+            continue;
+        }
+
+        match votes.iter_mut().find(|(movement, _)| *movement == first) {
+            Some((_, count)) => *count += 1,
+            None => votes.push((first, 1)),
+        }
+    }
+
+    // Ties go to the move first proposed.
+    let best = votes
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(movement, _)| movement);
+
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::observed_board;
+    use crate::{legal_pour_amounts, PourRule, Rules};
+
+    const HIDDEN: u8 = UNKNOWN_COLOR;
+
+    #[test]
+    fn planned_moves_are_legal_on_the_visible_board() {
+        let rules = Rules {
+            pour: PourRule::Partial,
+            ..Rules::default()
+        };
+        let state = observed_board(
+            3,
+            &[
+                &[1, 1, HIDDEN],
+                &[2, HIDDEN, HIDDEN],
+                &[1, 2, 2],
+                &[0, 0, 0],
+            ],
+            rules,
+        );
+
+        for seed in 0..4 {
+            let movement = plan_hidden_move(&state, 8, seed).unwrap().unwrap();
+            assert!(
+                legal_pour_amounts(&state, movement.from, movement.to)
+                    .any(|amount| amount == movement.amount),
+                "{:?}",
+                movement
+            );
+        }
+    }
+
+    #[test]
+    fn hidden_cells_must_complete_every_color() {
+        let state = observed_board(2, &[&[1, HIDDEN], &[1, HIDDEN], &[0, 2]], Rules::default());
+        assert!(plan_hidden_move(&state, 4, 0).is_err());
+    }
+}
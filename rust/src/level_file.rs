@@ -0,0 +1,214 @@
+use crate::{
+    convert_to_observed_state, is_final_state_game, legal_pour_amounts, move_liquid_state,
+    JsGameState, Lock, Movement, Pruning, Rules, State,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const LEVEL_FILE_VERSION: u32 = 1;
+
+/// The board fields are laid out as in a `SolveRequest`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LevelFile {
+    pub version: u32,
+    pub levels: usize,
+    pub n_of_bottles: usize,
+    pub bottles: Vec<Vec<u8>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacities: Option<Vec<usize>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locks: Option<Vec<Lock>>,
+    #[serde(default)]
+    pub rules: Rules,
+    #[serde(default)]
+    pub pruning: Pruning,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub par: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solution: Option<Vec<Movement>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl LevelFile {
+    fn game_state(&self) -> JsGameState {
+        JsGameState {
+            bottles: self.bottles.clone(),
+            capacities: self.capacities.clone(),
+            locks: self.locks.clone(),
+            rules: self.rules.clone(),
+            pruning: self.pruning,
+        }
+    }
+
+    fn state(&self) -> Result<State, String> {
+        convert_to_observed_state(&self.game_state(), self.levels, self.n_of_bottles)
+    }
+}
+
+/// The levels are the length of the bottles, which must all agree.
+fn migrate_game_state(game_state: JsGameState) -> Result<LevelFile, String> {
+    let levels = game_state.bottles.first().map_or(0, Vec::len);
+    if let Some(i) = game_state
+        .bottles
+        .iter()
+        .position(|bottle| bottle.len() != levels)
+    {
+        return Err(format!(
+            "Bottle {} has length {}, expected {} like the first bottle",
+            i,
+            game_state.bottles[i].len(),
+            levels
+        ));
+    }
+
+    Ok(LevelFile {
+        version: LEVEL_FILE_VERSION,
+        levels,
+        n_of_bottles: game_state.bottles.len(),
+        bottles: game_state.bottles,
+        capacities: game_state.capacities,
+        locks: game_state.locks,
+        rules: game_state.rules,
+        pruning: game_state.pruning,
+        par: None,
+        solution: None,
+        author: None,
+        tags: Vec::new(),
+    })
+}
+
+/// A solution can only be checked once nothing is hidden.
+fn validate(level: &LevelFile) -> Result<(), String> {
+    let mut state = level.state()?;
+
+    if let Some(par) = level.par {
+        if par == 0 {
+            return Err("Par must be at least 1".to_string());
+        }
+        if let Some(max_moves) = level.rules.max_moves.filter(|&max_moves| par > max_moves) {
+            return Err(format!("Par {} exceeds the move limit {}", par, max_moves));
+        }
+    }
+
+    let Some(solution) = &level.solution else {
+        return Ok(());
+    };
+    if state.has_hidden_cells() {
+        return Ok(());
+    }
+
+    for (i, movement) in solution.iter().enumerate() {
+        let Movement { from, to, amount } = *movement;
+        if from >= state.n_of_bottles
+            || to >= state.n_of_bottles
+            || from == to
+            || !legal_pour_amounts(&state, from, to).any(|legal| legal == amount)
+        {
+            return Err(format!("Solution move {} is not legal: {:?}", i, movement));
+        }
+        state = move_liquid_state(&state, from, to, amount);
+        state.previous_state = None;
+    }
+    if !is_final_state_game(&state) {
+        return Err("Solution does not solve the level".to_string());
+    }
+    Ok(())
+}
+
+pub(crate) fn load_level(json: &str) -> Result<LevelFile, String> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|e| format!("Invalid level file: {}", e))?;
+
+    let level = match value.get("version").map(Value::as_u64) {
+        None => {
+            let game_state: JsGameState =
+                serde_json::from_value(value).map_err(|e| format!("Invalid game state: {}", e))?;
+            migrate_game_state(game_state)?
+        }
+        Some(Some(version)) if version == LEVEL_FILE_VERSION as u64 => {
+            serde_json::from_value(value).map_err(|e| format!("Invalid level file: {}", e))?
+        }
+        Some(version) => {
+            return Err(format!(
+                "Unsupported level file version: {}",
+                version.map_or("not a number".to_string(), |v| v.to_string())
+            ));
+        }
+    };
+
+    validate(&level)?;
+    Ok(level)
+}
+
+pub(crate) fn save_level(level: &LevelFile) -> Result<String, String> {
+    validate(level)?;
+
+    let level = LevelFile {
+        version: LEVEL_FILE_VERSION,
+        ..level.clone()
+    };
+    serde_json::to_string_pretty(&level).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PourRule;
+
+    fn level() -> LevelFile {
+        LevelFile {
+            version: LEVEL_FILE_VERSION,
+            levels: 2,
+            n_of_bottles: 3,
+            bottles: vec![vec![1, 2], vec![2, 1], vec![0, 0]],
+            capacities: None,
+            locks: Some(vec![Lock::Open, Lock::Open, Lock::Moves(1)]),
+            rules: Rules {
+                pour: PourRule::Single,
+                max_moves: Some(10),
+                ..Rules::default()
+            },
+            pruning: Pruning {
+                skip_relabeling_pours: false,
+            },
+            par: Some(4),
+            solution: None,
+            author: Some("tester".to_string()),
+            tags: vec!["small".to_string()],
+        }
+    }
+
+    #[test]
+    fn save_then_load_round_trips() {
+        let saved = save_level(&level()).unwrap();
+        let loaded = load_level(&saved).unwrap();
+
+        assert!(!loaded.pruning.skip_relabeling_pours);
+        assert_eq!(loaded.rules, level().rules);
+        assert_eq!(loaded.par, Some(4));
+        assert_eq!(save_level(&loaded).unwrap(), saved);
+    }
+
+    #[test]
+    fn rejects_a_par_outside_the_move_limit() {
+        let mut level = level();
+        level.par = Some(0);
+        assert!(save_level(&level).is_err());
+
+        level.par = Some(11);
+        let json = serde_json::to_string(&level).unwrap();
+        assert!(load_level(&json).is_err());
+    }
+
+    #[test]
+    fn migrates_bare_game_states() {
+        let level = load_level(r#"{ "bottles": [[1, 2], [2, 1], [0, 0]] }"#).unwrap();
+
+        assert_eq!(level.version, LEVEL_FILE_VERSION);
+        assert_eq!((level.levels, level.n_of_bottles), (2, 3));
+        assert!(level.pruning.skip_relabeling_pours);
+    }
+}
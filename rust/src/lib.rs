@@ -1,38 +1,227 @@
 use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 
-const MAX_LEVELS: usize = 12;
-const MAX_N_OF_BOTTLES: usize = 12;
-
-#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+mod analysis;
+mod anytime;
+mod bidirectional;
+mod challenge;
+mod distance;
+mod extra_bottles;
+mod heuristics;
+mod hidden;
+mod level_file;
+mod mcts;
+mod messages;
+mod optimizer;
+#[cfg(not(target_arch = "wasm32"))]
+mod parallel;
+mod pattern_database;
+mod session;
+mod text_format;
+mod weighted_astar;
+
+#[cfg(test)]
+mod test_support;
+
+use analysis::is_dead_state;
+use anytime::anytime_search;
+use bidirectional::bidirectional_search;
+use challenge::bounded_search;
+use distance::{distance_to_goal as goal_distance, evaluate_moves as move_evaluations};
+pub use distance::{Distance, MoveEvaluation};
+use extra_bottles::plan_extra_bottles as plan_extra;
+pub use extra_bottles::ExtraBottlesPlan;
+use heuristics::{calculate_heuristic, heuristic_by_name, Greedy, Heuristic};
+use hidden::plan_hidden_move as plan_hidden;
+use level_file::{load_level, save_level};
+pub use level_file::{LevelFile, LEVEL_FILE_VERSION};
+use mcts::{mcts_search, MctsConfig};
+use messages::handle_request;
+pub use messages::{solve_batch, SolveRequest, SolveResponse};
+use optimizer::shorten_movements;
+#[cfg(not(target_arch = "wasm32"))]
+pub use parallel::solve_parallel;
+use pattern_database::{PatternDatabase, PatternDatabaseHeuristic};
+pub use session::GameSession;
+use text_format::{format_text, parse_text};
+use weighted_astar::weighted_astar_search;
+
+/// Boards and bottles up to these sizes are cloned without touching the heap.
+const INLINE_BOTTLES: usize = 12;
+const INLINE_LEVELS: usize = 16;
+const MAX_LEVELS: usize = 32;
+const MAX_N_OF_BOTTLES: usize = 32;
+const DEAD_END_SEARCH_LIMIT: usize = 20000;
+const BIDIRECTIONAL_MAX_BOTTLES: usize = 10;
+const BIDIRECTIONAL_MAX_STATES: usize = 400000;
+const WEIGHTED_ASTAR_MAX_EXPANSIONS: usize = 200000;
+const DISTANCE_MAX_EXPANSIONS: usize = 20000;
+const MCTS_DEFAULT_ITERATIONS: usize = 20000;
+const HIDDEN_PLAN_SAMPLES: usize = 16;
+const MAX_EXTRA_BOTTLES: usize = 3;
+
+/// Only `plan_hidden_move` and `GameSession` accept this color.
+const UNKNOWN_COLOR: u8 = u8::MAX;
+
+type Bottles = SmallVec<[Bottle; INLINE_BOTTLES]>;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
 struct Bottle {
-    state: [u8; MAX_LEVELS],
+    /// One cell per unit of capacity, bottom first.
+    state: SmallVec<[u8; INLINE_LEVELS]>,
     top_liquid: u8,
     liquid_level: usize,
 }
 
+// `SmallVec`'s derived `Clone` pushes cells one at a time; copy them in bulk.
+impl Clone for Bottle {
+    fn clone(&self) -> Self {
+        Self {
+            state: SmallVec::from_slice(&self.state),
+            top_liquid: self.top_liquid,
+            liquid_level: self.liquid_level,
+        }
+    }
+}
+
+// Cells above `liquid_level` are always empty, so hashing the liquid alone
+// stays consistent with the derived `Eq`.
+impl Hash for Bottle {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.state[0..self.liquid_level].hash(state);
+    }
+}
+
+impl Bottle {
+    fn empty(capacity: usize) -> Self {
+        Self {
+            state: SmallVec::from_elem(0, capacity),
+            top_liquid: 0,
+            liquid_level: 0,
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.state.len()
+    }
+}
+
+/// Once open, a bottle stays open.
+#[derive(
+    Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Lock {
+    #[default]
+    Open,
+    /// Until some bottle is full of this color.
+    Color(u8),
+    /// Until this many more moves have been made.
+    Moves(usize),
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum PourRule {
+    /// The whole top run of the source, or as much of it as fits.
+    #[default]
+    Full,
+    /// Exactly one unit.
+    Single,
+    /// Any amount up to what `Full` would move.
+    Partial,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum GoalMode {
+    /// Bottles need not be full.
+    #[default]
+    SingleColor,
+    /// No color is split across bottles.
+    FullBottles,
+    /// Top-down contents of every bottle, which may mix colors.
+    Layout(Arc<Vec<Vec<u8>>>),
+}
+
+/// Every field defaults to the classic game.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct Rules {
+    pub pour: PourRule,
+    pub goal: GoalMode,
+    /// Challenge mode.
+    pub max_moves: Option<usize>,
+}
+
+/// Moves the solvers skip; players may still make them.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct Pruning {
+    /// Not applied under a goal layout, where it matters which bottle holds
+    /// what, nor while a move lock counts down, where the pour uses up a turn.
+    pub skip_relabeling_pours: bool,
+}
+
+impl Default for Pruning {
+    fn default() -> Self {
+        Self {
+            skip_relabeling_pours: true,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct JsGameState {
     bottles: Vec<Vec<u8>>,
+    /// Every bottle holds `levels` units when absent.
+    #[serde(default)]
+    capacities: Option<Vec<usize>>,
+    /// Every bottle is open when absent.
+    #[serde(default)]
+    locks: Option<Vec<Lock>>,
+    #[serde(default)]
+    rules: Rules,
+    #[serde(default)]
+    pruning: Pruning,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-struct Movement {
-    from: usize,
-    to: usize,
-    amount: usize,
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Movement {
+    pub from: usize,
+    pub to: usize,
+    pub amount: usize,
+}
+
+/// Fixed for a whole puzzle, so every state of a search shares one copy.
+#[derive(Clone, Debug, Default)]
+struct Settings {
+    rules: Rules,
+    pruning: Pruning,
 }
 
+/// One lock per bottle; `None` once every bottle is open.
+type Locks = Option<Box<[Lock]>>;
+
+/// What tells two states apart, as the transposition tables key them.
+type Position = (Bottles, Locks);
+
 #[derive(Clone, Debug)]
 struct State {
-    state: [Bottle; MAX_N_OF_BOTTLES],
-    previous_state: Option<Box<State>>,
+    state: Bottles,
+    locks: Locks,
+    /// Shared, so cloning a state never copies its history.
+    previous_state: Option<Arc<State>>,
     last_movement: Option<(usize, usize)>,
     how_much_moved: usize,
     levels: usize,
     n_of_bottles: usize,
+    settings: Arc<Settings>,
 }
 
 impl Hash for State {
@@ -45,18 +234,147 @@ impl PartialEq for State {
     fn eq(&self, other: &Self) -> bool {
         self.n_of_bottles == other.n_of_bottles
             && self.state[0..self.n_of_bottles] == other.state[0..other.n_of_bottles]
+            && self.locks == other.locks
     }
 }
 
 impl Eq for State {}
 
+impl State {
+    /// Assumed by the pattern databases and bidirectional search.
+    fn has_uniform_capacity(&self) -> bool {
+        self.state
+            .iter()
+            .all(|bottle| bottle.capacity() == self.levels)
+    }
+
+    fn has_hidden_cells(&self) -> bool {
+        self.state
+            .iter()
+            .any(|bottle| bottle.state[0..bottle.liquid_level].contains(&UNKNOWN_COLOR))
+    }
+
+    fn has_locks(&self) -> bool {
+        self.locks.is_some()
+    }
+
+    fn lock(&self, bottle: usize) -> Lock {
+        self.locks
+            .as_ref()
+            .map_or(Lock::Open, |locks| locks[bottle])
+    }
+
+    fn position(&self) -> Position {
+        (self.state.clone(), self.locks.clone())
+    }
+
+    fn rules(&self) -> &Rules {
+        &self.settings.rules
+    }
+
+    fn rules_mut(&mut self) -> &mut Rules {
+        &mut Arc::make_mut(&mut self.settings).rules
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Strategy {
+    IdaStar,
+    EnhancedBfs,
+    Bidirectional,
+    Beam(usize),
+    WeightedAStar(f64),
+    Mcts(usize),
+}
+
+impl Strategy {
+    fn name(&self) -> &'static str {
+        match self {
+            Strategy::IdaStar => "ida-star",
+            Strategy::EnhancedBfs => "enhanced-bfs",
+            Strategy::Bidirectional => "bidirectional",
+            Strategy::Beam(_) => "beam",
+            Strategy::WeightedAStar(_) => "weighted-astar",
+            Strategy::Mcts(_) => "mcts",
+        }
+    }
+
+    /// E.g. `beam:500`, `weighted-astar:1.5` or `mcts:20000`.
+    fn parse(text: &str) -> Result<Self, String> {
+        let (name, parameter) = match text.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter)),
+            None => (text, None),
+        };
+
+        fn parse_or<T: std::str::FromStr>(
+            parameter: Option<&str>,
+            default: T,
+        ) -> Result<T, String> {
+            match parameter {
+                Some(value) => value
+                    .parse()
+                    .map_err(|_| format!("Invalid strategy parameter: {}", value)),
+                None => Ok(default),
+            }
+        }
+
+        match name {
+            "ida-star" => Ok(Strategy::IdaStar),
+            "enhanced-bfs" => Ok(Strategy::EnhancedBfs),
+            "bidirectional" => Ok(Strategy::Bidirectional),
+            "beam" => match parse_or(parameter, 1000)? {
+                0 => Err("Invalid beam width: 0. Must be at least 1.".to_string()),
+                width => Ok(Strategy::Beam(width)),
+            },
+            "weighted-astar" => {
+                let weight = parse_or(parameter, 2.0)?;
+                validate_weight(weight)?;
+                Ok(Strategy::WeightedAStar(weight))
+            }
+            "mcts" => match parse_or(parameter, MCTS_DEFAULT_ITERATIONS)? {
+                0 => Err("Invalid MCTS iterations: 0. Must be at least 1.".to_string()),
+                iterations => Ok(Strategy::Mcts(iterations)),
+            },
+            _ => Err(format!("Unknown strategy: {}", name)),
+        }
+    }
+}
+
+fn validate_weight(weight: f64) -> Result<(), String> {
+    if weight >= 1.0 && weight.is_finite() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid weight: {}. Must be a finite number >= 1.",
+            weight
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SearchStats {
+    pub strategy: String,
+    pub solved: bool,
+    pub solution_length: usize,
+    /// `None` for a cached solution.
+    pub nodes_expanded: Option<usize>,
+    pub elapsed_ms: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct StrategyOutcome {
+    movements: Vec<Movement>,
+    stats: SearchStats,
+}
+
 struct SearchResult {
     cost: f64,
     is_goal: bool,
 }
 
 struct PatternCache {
-    cache: HashMap<String, Vec<Movement>>,
+    /// Every state of a solution shares its moves, from the offset on.
+    cache: HashMap<String, (Arc<[Movement]>, usize)>,
 }
 
 impl PatternCache {
@@ -67,9 +385,13 @@ impl PatternCache {
     }
 
     fn create_key(state: &State) -> String {
-        let mut key = String::new();
+        let mut key = format!("{:?}", state.rules());
         for i in 0..state.n_of_bottles {
             let bottle = &state.state[i];
+            key.push_str(&bottle.capacity().to_string());
+            if state.lock(i) != Lock::Open {
+                key.push_str(&format!("{:?}", state.lock(i)));
+            }
             key.push('[');
             for j in 0..bottle.liquid_level {
                 key.push_str(&bottle.state[j].to_string());
@@ -80,54 +402,111 @@ impl PatternCache {
         key
     }
 
-    fn get(&self, state: &State) -> Option<&Vec<Movement>> {
+    fn get(&self, state: &State) -> Option<&[Movement]> {
         let key = Self::create_key(state);
-        self.cache.get(&key)
+        self.cache
+            .get(&key)
+            .map(|(movements, offset)| &movements[*offset..])
     }
 
-    fn put(&mut self, state: &State, movements: Vec<Movement>) {
-        let key = Self::create_key(state);
-        self.cache.insert(key, movements);
+    /// Also caches every intermediate state with its remaining moves.
+    fn put_solution(&mut self, state: &State, movements: &[Movement]) {
+        let solution: Arc<[Movement]> = movements.into();
+        let mut current = state.clone();
+        for (i, movement) in movements.iter().enumerate() {
+            self.cache
+                .insert(Self::create_key(&current), (solution.clone(), i));
+            current = move_liquid_state(&current, movement.from, movement.to, movement.amount);
+            current.previous_state = None;
+        }
     }
 }
 
-static mut PATTERN_CACHE: Option<PatternCache> = None;
+thread_local! {
+    static PATTERN_CACHE: RefCell<PatternCache> = RefCell::new(PatternCache::new());
+    static PATTERN_DATABASES: RefCell<Vec<Arc<PatternDatabase>>> = const { RefCell::new(Vec::new()) };
+}
 
-#[wasm_bindgen]
-pub fn solve(
-    levels: usize,
-    n_of_bottles: usize,
-    game_state_js: JsValue,
-) -> Result<JsValue, JsError> {
-    if levels == 0 || levels > MAX_LEVELS {
-        return Err(JsError::new(&format!(
-            "Invalid levels: {}. Must be > 0 and <= {}.",
-            levels, MAX_LEVELS
-        )));
+fn strategy_pipeline(state: &State) -> Vec<Strategy> {
+    let mut pipeline = vec![Strategy::IdaStar, Strategy::EnhancedBfs];
+    if state.n_of_bottles <= BIDIRECTIONAL_MAX_BOTTLES {
+        pipeline.push(Strategy::Bidirectional);
     }
-    if n_of_bottles == 0 || n_of_bottles > MAX_N_OF_BOTTLES {
-        return Err(JsError::new(&format!(
-            "Invalid n_of_bottles: {}. Must be > 0 and <= {}.",
-            n_of_bottles, MAX_N_OF_BOTTLES
-        )));
+    pipeline.push(Strategy::Beam(1000));
+    pipeline
+}
+
+fn run_strategy(strategy: Strategy, state: State) -> (Option<State>, SearchStats) {
+    let started = now_ms();
+    let mut nodes_expanded = 0;
+
+    let solution = match strategy {
+        Strategy::IdaStar => ida_star_search(state, &Greedy, &mut nodes_expanded),
+        Strategy::EnhancedBfs => enhanced_bfs_search(state, &mut nodes_expanded),
+        Strategy::Bidirectional => {
+            bidirectional_search(state, BIDIRECTIONAL_MAX_STATES, &mut nodes_expanded)
+        }
+        Strategy::Beam(beam_width) => {
+            beam_search(state, beam_width, f64::INFINITY, &mut nodes_expanded)
+        }
+        Strategy::WeightedAStar(weight) => weighted_astar_search(
+            state,
+            &admissible_heuristic(),
+            weight,
+            WEIGHTED_ASTAR_MAX_EXPANSIONS,
+            &mut nodes_expanded,
+        ),
+        Strategy::Mcts(iterations) => {
+            mcts_search(state, &MctsConfig::new(iterations), &mut nodes_expanded)
+        }
     }
+    .filter(is_final_state_game);
+
+    let stats = SearchStats {
+        strategy: strategy.name().to_string(),
+        solved: solution.is_some(),
+        solution_length: solution
+            .as_ref()
+            .map_or(0, |solution| extract_movements(solution.clone()).len()),
+        nodes_expanded: Some(nodes_expanded),
+        elapsed_ms: now_ms() - started,
+    };
+
+    (solution, stats)
+}
 
+fn admissible_heuristic() -> PatternDatabaseHeuristic {
+    PatternDatabaseHeuristic::new(PATTERN_DATABASES.with(|databases| databases.borrow().clone()))
+}
+
+fn parse_game_state(
+    levels: usize,
+    n_of_bottles: usize,
+    game_state_js: JsValue,
+) -> Result<State, JsError> {
     let game_state: JsGameState = serde_wasm_bindgen::from_value(game_state_js)
         .map_err(|e| JsError::new(&format!("Failed to parse game state: {}", e)))?;
 
-    let initial_state = convert_to_internal_state(&game_state, levels, n_of_bottles)?;
-
-    unsafe {
-        if PATTERN_CACHE.is_none() {
-            PATTERN_CACHE = Some(PatternCache::new());
-        }
+    convert_to_internal_state(&game_state, levels, n_of_bottles).map_err(|e| JsError::new(&e))
+}
 
-        if let Some(cache) = &PATTERN_CACHE {
-            if let Some(movements) = cache.get(&initial_state) {
-                return Ok(serde_wasm_bindgen::to_value(movements)
-                    .map_err(|e| JsError::new(&format!("Failed to serialize solution: {}", e)))?);
-            }
-        }
+/// The stats describe the strategy that produced the solution.
+/// Under a move limit, `bounded_search` settles whatever the pipeline does not.
+fn solve_state(initial_state: &State) -> Result<StrategyOutcome, String> {
+    let started = now_ms();
+
+    let cached = PATTERN_CACHE.with(|cache| cache.borrow().get(initial_state).map(<[_]>::to_vec));
+    if let Some(movements) = cached {
+        return Ok(StrategyOutcome {
+            stats: SearchStats {
+                strategy: "cache".to_string(),
+                solved: true,
+                solution_length: movements.len(),
+                nodes_expanded: None,
+                elapsed_ms: now_ms() - started,
+            },
+            movements,
+        });
     }
 
     let empty_bottles = initial_state.state[0..initial_state.n_of_bottles]
@@ -135,86 +514,475 @@ pub fn solve(
         .filter(|b| b.liquid_level == 0)
         .count();
 
-    let optimized_state = if empty_bottles >= 3 {
-        optimize_initial_distribution(initial_state.clone())
-    } else {
-        initial_state.clone()
-    };
-
-    if let Some(solution) = ida_star_search(optimized_state) {
-        let movements = extract_movements(solution);
+    let mut attempts = Vec::new();
+    if empty_bottles >= 3 {
+        let optimized_state = optimize_initial_distribution(initial_state.clone());
+        attempts.push((Strategy::IdaStar, optimized_state));
+    }
+    for strategy in strategy_pipeline(initial_state) {
+        attempts.push((strategy, initial_state.clone()));
+    }
 
-        unsafe {
-            if let Some(cache) = &mut PATTERN_CACHE {
-                cache.put(&initial_state, movements.clone());
+    for (strategy, start) in attempts {
+        let (solution, mut stats) = run_strategy(strategy, start);
+        if let Some(solution) = solution {
+            let movements = shorten_movements(initial_state, extract_movements(solution));
+            if initial_state
+                .rules()
+                .max_moves
+                .is_some_and(|max_moves| movements.len() > max_moves)
+            {
+                break;
             }
-        }
 
-        return Ok(serde_wasm_bindgen::to_value(&movements)
-            .map_err(|e| JsError::new(&format!("Failed to serialize solution: {}", e)))?);
+            PATTERN_CACHE.with(|cache| cache.borrow_mut().put_solution(initial_state, &movements));
+
+            stats.solution_length = movements.len();
+            stats.elapsed_ms = now_ms() - started;
+            return Ok(StrategyOutcome { movements, stats });
+        }
     }
 
-    if let Some(solution) = ida_star_search(initial_state.clone()) {
-        let movements = extract_movements(solution);
+    let Some(max_moves) = initial_state.rules().max_moves else {
+        return Err("No solution found".to_string());
+    };
 
-        unsafe {
-            if let Some(cache) = &mut PATTERN_CACHE {
-                cache.put(&initial_state, movements.clone());
-            }
-        }
+    let mut nodes_expanded = 0;
+    let solution = bounded_search(
+        initial_state.clone(),
+        max_moves,
+        &admissible_heuristic(),
+        &mut nodes_expanded,
+    )
+    .ok_or_else(|| format!("No solution within {} moves", max_moves))?;
+    let movements = extract_movements(solution);
+
+    PATTERN_CACHE.with(|cache| cache.borrow_mut().put_solution(initial_state, &movements));
+
+    Ok(StrategyOutcome {
+        stats: SearchStats {
+            strategy: "bounded-ida-star".to_string(),
+            solved: true,
+            solution_length: movements.len(),
+            nodes_expanded: Some(nodes_expanded),
+            elapsed_ms: now_ms() - started,
+        },
+        movements,
+    })
+}
+
+#[wasm_bindgen]
+pub fn solve(
+    levels: usize,
+    n_of_bottles: usize,
+    game_state_js: JsValue,
+) -> Result<JsValue, JsError> {
+    let initial_state = parse_game_state(levels, n_of_bottles, game_state_js)?;
+    let outcome = solve_state(&initial_state).map_err(|e| JsError::new(&e))?;
+
+    serde_wasm_bindgen::to_value(&outcome.movements)
+        .map_err(|e| JsError::new(&format!("Failed to serialize solution: {}", e)))
+}
+
+/// Solver errors are answered in the response; only an invalid request is
+/// rejected.
+#[wasm_bindgen]
+pub fn handle_solve_request(request_js: JsValue) -> Result<JsValue, JsError> {
+    let request: SolveRequest = serde_wasm_bindgen::from_value(request_js)
+        .map_err(|e| JsError::new(&format!("Failed to parse solve request: {}", e)))?;
+
+    serde_wasm_bindgen::to_value(&handle_request(request))
+        .map_err(|e| JsError::new(&format!("Failed to serialize solve response: {}", e)))
+}
+
+#[wasm_bindgen(js_name = solve_batch)]
+pub fn solve_batch_js(puzzles_js: JsValue) -> Result<JsValue, JsError> {
+    let puzzles: Vec<SolveRequest> = serde_wasm_bindgen::from_value(puzzles_js)
+        .map_err(|e| JsError::new(&format!("Failed to parse puzzles: {}", e)))?;
+
+    serde_wasm_bindgen::to_value(&solve_batch(puzzles))
+        .map_err(|e| JsError::new(&format!("Failed to serialize batch results: {}", e)))
+}
 
-        return Ok(serde_wasm_bindgen::to_value(&movements)
-            .map_err(|e| JsError::new(&format!("Failed to serialize solution: {}", e)))?);
+#[wasm_bindgen]
+pub fn solve_optimal(
+    levels: usize,
+    n_of_bottles: usize,
+    game_state_js: JsValue,
+    heuristic_name: Option<String>,
+) -> Result<JsValue, JsError> {
+    let initial_state = parse_game_state(levels, n_of_bottles, game_state_js)?;
+
+    let heuristic: Box<dyn Heuristic> = match heuristic_name.as_deref() {
+        None | Some("pattern-database") => Box::new(admissible_heuristic()),
+        Some(name) => heuristic_by_name(name)
+            .ok_or_else(|| JsError::new(&format!("Unknown heuristic: {}", name)))?,
+    };
+    if !heuristic.is_admissible() {
+        return Err(JsError::new(&format!(
+            "Heuristic {} is not admissible and cannot be used for optimal search",
+            heuristic.name()
+        )));
     }
 
-    if let Some(solution) = enhanced_bfs_search(initial_state.clone()) {
-        let movements = extract_movements(solution);
+    let solution = ida_star_search(initial_state, heuristic.as_ref(), &mut 0)
+        .ok_or_else(|| JsError::new("No solution found"))?;
 
-        unsafe {
-            if let Some(cache) = &mut PATTERN_CACHE {
-                cache.put(&initial_state, movements.clone());
-            }
+    serde_wasm_bindgen::to_value(&extract_movements(solution))
+        .map_err(|e| JsError::new(&format!("Failed to serialize solution: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn solve_weighted(
+    levels: usize,
+    n_of_bottles: usize,
+    game_state_js: JsValue,
+    weight: f64,
+) -> Result<JsValue, JsError> {
+    validate_weight(weight).map_err(|e| JsError::new(&e))?;
+
+    let initial_state = parse_game_state(levels, n_of_bottles, game_state_js)?;
+
+    let solution = run_strategy(Strategy::WeightedAStar(weight), initial_state.clone())
+        .0
+        .ok_or_else(|| JsError::new("No solution found"))?;
+    let movements = shorten_movements(&initial_state, extract_movements(solution));
+
+    serde_wasm_bindgen::to_value(&movements)
+        .map_err(|e| JsError::new(&format!("Failed to serialize solution: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn solve_anytime(
+    levels: usize,
+    n_of_bottles: usize,
+    game_state_js: JsValue,
+    time_budget_ms: f64,
+    on_improvement: &js_sys::Function,
+) -> Result<JsValue, JsError> {
+    let initial_state = parse_game_state(levels, n_of_bottles, game_state_js)?;
+    let deadline = now_ms() + time_budget_ms;
+
+    let mut callback_error = None;
+    let best = anytime_search(&initial_state, deadline, &mut |movements| {
+        if callback_error.is_some() {
+            return;
         }
+        let result = serde_wasm_bindgen::to_value(movements)
+            .map_err(|e| format!("Failed to serialize solution: {}", e))
+            .and_then(|value| {
+                on_improvement
+                    .call1(&JsValue::NULL, &value)
+                    .map(|_| ())
+                    .map_err(|e| format!("on_improvement callback failed: {:?}", e))
+            });
+        callback_error = result.err();
+    });
 
-        return Ok(serde_wasm_bindgen::to_value(&movements)
-            .map_err(|e| JsError::new(&format!("Failed to serialize solution: {}", e)))?);
+    if let Some(error) = callback_error {
+        return Err(JsError::new(&error));
     }
 
-    if let Some(solution) = beam_search(initial_state.clone(), 1000) {
-        let movements = extract_movements(solution);
+    let movements = best.ok_or_else(|| JsError::new("No solution found"))?;
+    serde_wasm_bindgen::to_value(&movements)
+        .map_err(|e| JsError::new(&format!("Failed to serialize solution: {}", e)))
+}
+
+/// Runs one strategy without the cache or post-processing.
+#[wasm_bindgen]
+pub fn solve_with_strategy(
+    levels: usize,
+    n_of_bottles: usize,
+    game_state_js: JsValue,
+    strategy: &str,
+) -> Result<JsValue, JsError> {
+    let strategy = Strategy::parse(strategy).map_err(|e| JsError::new(&e))?;
+    let initial_state = parse_game_state(levels, n_of_bottles, game_state_js)?;
 
-        unsafe {
-            if let Some(cache) = &mut PATTERN_CACHE {
-                cache.put(&initial_state, movements.clone());
-            }
-        }
+    let (solution, stats) = run_strategy(strategy, initial_state);
+    let outcome = StrategyOutcome {
+        movements: solution.map(extract_movements).unwrap_or_default(),
+        stats,
+    };
+
+    serde_wasm_bindgen::to_value(&outcome)
+        .map_err(|e| JsError::new(&format!("Failed to serialize solution: {}", e)))
+}
 
-        return Ok(serde_wasm_bindgen::to_value(&movements)
-            .map_err(|e| JsError::new(&format!("Failed to serialize solution: {}", e)))?);
+/// `null` if none of the sampled completions could be solved.
+#[wasm_bindgen]
+pub fn plan_hidden_move(
+    levels: usize,
+    n_of_bottles: usize,
+    game_state_js: JsValue,
+    samples: Option<usize>,
+) -> Result<JsValue, JsError> {
+    let game_state: JsGameState = serde_wasm_bindgen::from_value(game_state_js)
+        .map_err(|e| JsError::new(&format!("Failed to parse game state: {}", e)))?;
+    let state = convert_to_observed_state(&game_state, levels, n_of_bottles)
+        .map_err(|e| JsError::new(&e))?;
+
+    let movement = plan_hidden(
+        &state,
+        samples.unwrap_or(HIDDEN_PLAN_SAMPLES),
+        now_ms() as u64,
+    )
+    .map_err(|e| JsError::new(&e))?;
+
+    serde_wasm_bindgen::to_value(&movement)
+        .map_err(|e| JsError::new(&format!("Failed to serialize movement: {}", e)))
+}
+
+#[derive(Serialize)]
+struct TextPuzzle {
+    levels: usize,
+    n_of_bottles: usize,
+    game_state: JsGameState,
+}
+
+#[wasm_bindgen]
+pub fn parse_puzzle_text(text: &str) -> Result<JsValue, JsError> {
+    let state = parse_text(text).map_err(|e| JsError::new(&e))?;
+    let puzzle = TextPuzzle {
+        levels: state.levels,
+        n_of_bottles: state.n_of_bottles,
+        game_state: convert_to_js_state(&state),
+    };
+
+    serde_wasm_bindgen::to_value(&puzzle)
+        .map_err(|e| JsError::new(&format!("Failed to serialize puzzle: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn format_puzzle_text(
+    levels: usize,
+    n_of_bottles: usize,
+    game_state_js: JsValue,
+) -> Result<String, JsError> {
+    let game_state: JsGameState = serde_wasm_bindgen::from_value(game_state_js)
+        .map_err(|e| JsError::new(&format!("Failed to parse game state: {}", e)))?;
+    let state = convert_to_observed_state(&game_state, levels, n_of_bottles)
+        .map_err(|e| JsError::new(&e))?;
+
+    format_text(&state).map_err(|e| JsError::new(&e))
+}
+
+/// Also migrates a bare `{ bottles }` game state.
+#[wasm_bindgen]
+pub fn load_level_file(json: &str) -> Result<JsValue, JsError> {
+    let level = load_level(json).map_err(|e| JsError::new(&e))?;
+
+    serde_wasm_bindgen::to_value(&level)
+        .map_err(|e| JsError::new(&format!("Failed to serialize level: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn save_level_file(level_js: JsValue) -> Result<String, JsError> {
+    let level: LevelFile = serde_wasm_bindgen::from_value(level_js)
+        .map_err(|e| JsError::new(&format!("Failed to parse level: {}", e)))?;
+
+    save_level(&level).map_err(|e| JsError::new(&e))
+}
+
+#[wasm_bindgen]
+pub fn distance_to_goal(
+    levels: usize,
+    n_of_bottles: usize,
+    game_state_js: JsValue,
+) -> Result<JsValue, JsError> {
+    let state = parse_game_state(levels, n_of_bottles, game_state_js)?;
+
+    serde_wasm_bindgen::to_value(&goal_distance(&state))
+        .map_err(|e| JsError::new(&format!("Failed to serialize distance: {}", e)))
+}
+
+/// The moves share one search budget, so some may only get a lower bound.
+#[wasm_bindgen]
+pub fn evaluate_moves(
+    levels: usize,
+    n_of_bottles: usize,
+    game_state_js: JsValue,
+) -> Result<JsValue, JsError> {
+    let state = parse_game_state(levels, n_of_bottles, game_state_js)?;
+
+    serde_wasm_bindgen::to_value(&move_evaluations(&state))
+        .map_err(|e| JsError::new(&format!("Failed to serialize move evaluations: {}", e)))
+}
+
+/// `max_extra` defaults to 3.
+#[wasm_bindgen]
+pub fn plan_extra_bottles(
+    levels: usize,
+    n_of_bottles: usize,
+    game_state_js: JsValue,
+    max_extra: Option<usize>,
+) -> Result<JsValue, JsError> {
+    let game_state: JsGameState = serde_wasm_bindgen::from_value(game_state_js)
+        .map_err(|e| JsError::new(&format!("Failed to parse game state: {}", e)))?;
+
+    let plan = plan_extra(
+        &game_state,
+        levels,
+        n_of_bottles,
+        max_extra.unwrap_or(MAX_EXTRA_BOTTLES),
+    )
+    .map_err(|e| JsError::new(&e))?;
+
+    serde_wasm_bindgen::to_value(&plan)
+        .map_err(|e| JsError::new(&format!("Failed to serialize plan: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn is_dead_end(
+    levels: usize,
+    n_of_bottles: usize,
+    game_state_js: JsValue,
+) -> Result<bool, JsError> {
+    let state = parse_game_state(levels, n_of_bottles, game_state_js)?;
+    Ok(analysis::is_dead_end(&state, DEAD_END_SEARCH_LIMIT))
+}
+
+#[wasm_bindgen]
+pub fn generate_pattern_database(
+    levels: usize,
+    n_of_bottles: usize,
+    game_state_js: JsValue,
+    colors: Vec<u8>,
+    max_entries: usize,
+) -> Result<Vec<u8>, JsError> {
+    let template = parse_game_state(levels, n_of_bottles, game_state_js)?;
+    if !template.has_uniform_capacity() {
+        return Err(JsError::new(
+            "Pattern databases require every bottle to hold `levels` units",
+        ));
     }
 
-    Err(JsError::new("No solution found"))
+    if colors.len() > MAX_N_OF_BOTTLES {
+        return Err(JsError::new(&format!(
+            "Pattern databases track at most {} colors",
+            MAX_N_OF_BOTTLES
+        )));
+    }
+
+    Ok(PatternDatabase::generate(&template, &colors, max_entries).to_bytes())
+}
+
+#[wasm_bindgen]
+pub fn load_pattern_database(bytes: &[u8]) -> Result<usize, JsError> {
+    let database = PatternDatabase::from_bytes(bytes)
+        .map_err(|e| JsError::new(&format!("Failed to parse pattern database: {}", e)))?;
+    let entries = database.len();
+
+    PATTERN_DATABASES.with(|databases| databases.borrow_mut().push(Arc::new(database)));
+
+    Ok(entries)
 }
 
 fn convert_to_internal_state(
     game_state_from_js: &JsGameState,
     levels: usize,
     n_of_bottles: usize,
-) -> Result<State, JsError> {
-    let mut internal_bottles_array = [Bottle {
-        state: [0; MAX_LEVELS],
-        top_liquid: 0,
-        liquid_level: 0,
-    }; MAX_N_OF_BOTTLES];
+) -> Result<State, String> {
+    let state = convert_to_observed_state(game_state_from_js, levels, n_of_bottles)?;
+    if state.has_hidden_cells() {
+        return Err(format!(
+            "Game state has hidden cells (color {}); plan with plan_hidden_move instead",
+            UNKNOWN_COLOR
+        ));
+    }
+    Ok(state)
+}
+
+/// The inverse of `convert_to_observed_state`.
+fn convert_to_js_state(state: &State) -> JsGameState {
+    let bottles = &state.state[0..state.n_of_bottles];
+
+    JsGameState {
+        bottles: bottles
+            .iter()
+            .map(|bottle| {
+                (0..state.levels)
+                    .rev()
+                    .map(|k| {
+                        if k < bottle.liquid_level {
+                            bottle.state[k]
+                        } else {
+                            0
+                        }
+                    })
+                    .collect()
+            })
+            .collect(),
+        capacities: (!state.has_uniform_capacity())
+            .then(|| bottles.iter().map(|bottle| bottle.capacity()).collect()),
+        locks: state.locks.as_ref().map(|locks| locks.to_vec()),
+        rules: state.rules().clone(),
+        pruning: state.settings.pruning,
+    }
+}
+
+/// Like `convert_to_internal_state`, but also accepts `UNKNOWN_COLOR` cells.
+fn convert_to_observed_state(
+    game_state_from_js: &JsGameState,
+    levels: usize,
+    n_of_bottles: usize,
+) -> Result<State, String> {
+    if levels == 0 || levels > MAX_LEVELS {
+        return Err(format!(
+            "Invalid levels: {}. Must be > 0 and <= {}.",
+            levels, MAX_LEVELS
+        ));
+    }
+    if n_of_bottles == 0 || n_of_bottles > MAX_N_OF_BOTTLES {
+        return Err(format!(
+            "Invalid n_of_bottles: {}. Must be > 0 and <= {}.",
+            n_of_bottles, MAX_N_OF_BOTTLES
+        ));
+    }
 
     if game_state_from_js.bottles.len() != n_of_bottles {
-        return Err(JsError::new(&format!(
+        return Err(format!(
             "JS GameState has {} bottles, expected {}",
             game_state_from_js.bottles.len(),
             n_of_bottles
-        )));
+        ));
+    }
+
+    let capacities = match &game_state_from_js.capacities {
+        Some(capacities) => {
+            if capacities.len() != n_of_bottles {
+                return Err(format!(
+                    "JS GameState has {} capacities, expected {}",
+                    capacities.len(),
+                    n_of_bottles
+                ));
+            }
+            if let Some(i) = capacities.iter().position(|&c| c == 0 || c > levels) {
+                return Err(format!(
+                    "Invalid capacity for bottle {}: {}. Must be > 0 and <= {}.",
+                    i, capacities[i], levels
+                ));
+            }
+            capacities.clone()
+        }
+        None => vec![levels; n_of_bottles],
+    };
+
+    if let Some(locks) = &game_state_from_js.locks {
+        if locks.len() != n_of_bottles {
+            return Err(format!(
+                "JS GameState has {} locks, expected {}",
+                locks.len(),
+                n_of_bottles
+            ));
+        }
     }
 
+    let mut internal_bottles_array: Bottles = capacities
+        .iter()
+        .map(|&capacity| Bottle::empty(capacity))
+        .collect();
+
     for (i, js_bottle_vec) in game_state_from_js.bottles.iter().enumerate() {
         if i >= n_of_bottles {
             break;
@@ -223,36 +991,33 @@ fn convert_to_internal_state(
         let js_vec_len = js_bottle_vec.len();
         if js_vec_len != levels {
             if js_vec_len > levels {
-                return Err(JsError::new(&format!(
+                return Err(format!(
                     "Bottle {} from JS has length {}, exceeds game levels {}",
                     i, js_vec_len, levels
-                )));
+                ));
             }
-            return Err(JsError::new(&format!(
+            return Err(format!(
                 "Bottle {} from JS has length {}, expected game levels {}",
                 i, js_vec_len, levels
-            )));
+            ));
         }
 
-        let mut temp_bottle_state = [0u8; MAX_LEVELS];
-
         let mut internal_idx = 0;
         for &color in js_bottle_vec.iter().rev() {
             if color != 0 {
-                if internal_idx < levels {
-                    temp_bottle_state[internal_idx] = color;
+                if internal_idx < capacities[i] {
+                    internal_bottles_array[i].state[internal_idx] = color;
                     internal_idx += 1;
                 } else {
-                    return Err(JsError::new(&format!(
-                        "Too much liquid in JS bottle {} for specified levels {}",
-                        i, levels
-                    )));
+                    return Err(format!(
+                        "Too much liquid in JS bottle {} for its capacity {}",
+                        i, capacities[i]
+                    ));
                 }
             }
         }
 
         let current_liquid_level = internal_idx;
-        internal_bottles_array[i].state = temp_bottle_state;
         internal_bottles_array[i].liquid_level = current_liquid_level;
 
         if current_liquid_level > 0 {
@@ -263,36 +1028,91 @@ fn convert_to_internal_state(
         }
     }
 
-    Ok(State {
+    let mut state = State {
         state: internal_bottles_array,
+        locks: game_state_from_js
+            .locks
+            .as_ref()
+            .map(|locks| locks.as_slice().into()),
         previous_state: None,
         last_movement: None,
         how_much_moved: 0,
         levels,
         n_of_bottles,
-    })
-}
+        settings: Arc::new(Settings {
+            rules: game_state_from_js.rules.clone(),
+            pruning: game_state_from_js.pruning,
+        }),
+    };
+    release_locks(&mut state);
 
-fn is_valid_to_move(from_bottle: &Bottle, to_bottle: &Bottle, levels: usize) -> bool {
-    if from_bottle.liquid_level == 0 || to_bottle.liquid_level >= levels {
-        return false;
+    if let GoalMode::Layout(layout) = &state.rules().goal {
+        validate_layout(&state, layout)?;
     }
 
-    if to_bottle.liquid_level == 0 {
-        let top_color = from_bottle.top_liquid;
-        let is_single_color =
-            (0..from_bottle.liquid_level).all(|i| from_bottle.state[i] == top_color);
+    Ok(state)
+}
 
-        if is_single_color {
-            return false;
+fn validate_layout(state: &State, layout: &[Vec<u8>]) -> Result<(), String> {
+    if layout.len() != state.n_of_bottles {
+        return Err(format!(
+            "Goal layout has {} bottles, expected {}",
+            layout.len(),
+            state.n_of_bottles
+        ));
+    }
+
+    let mut difference: HashMap<u8, isize> = HashMap::new();
+    for (i, (target, bottle)) in layout.iter().zip(&state.state).enumerate() {
+        if target.len() != state.levels {
+            return Err(format!(
+                "Goal layout bottle {} has length {}, expected game levels {}",
+                i,
+                target.len(),
+                state.levels
+            ));
+        }
+        if target.iter().filter(|&&color| color != 0).count() > bottle.capacity() {
+            return Err(format!(
+                "Goal layout bottle {} holds more than its capacity {}",
+                i,
+                bottle.capacity()
+            ));
+        }
+        for &color in target.iter().filter(|&&color| color != 0) {
+            *difference.entry(color).or_default() += 1;
         }
+        for &color in &bottle.state[0..bottle.liquid_level] {
+            *difference.entry(color).or_default() -= 1;
+        }
+    }
+
+    if !state.has_hidden_cells() && difference.values().any(|&units| units != 0) {
+        return Err("Goal layout does not use the same liquid as the puzzle".to_string());
+    }
+    Ok(())
+}
+
+fn is_valid_to_move(from_bottle: &Bottle, to_bottle: &Bottle) -> bool {
+    if from_bottle.liquid_level == 0 || to_bottle.liquid_level >= to_bottle.capacity() {
+        return false;
+    }
+
+    if from_bottle.top_liquid == UNKNOWN_COLOR || to_bottle.top_liquid == UNKNOWN_COLOR {
+        return false;
     }
 
     to_bottle.liquid_level == 0 || from_bottle.top_liquid == to_bottle.top_liquid
 }
 
-fn how_much_to_move(from_bottle: &Bottle, to_bottle: &Bottle, levels: usize) -> usize {
-    if !is_valid_to_move(from_bottle, to_bottle, levels) {
+fn is_relabeling_pour(from_bottle: &Bottle, to_bottle: &Bottle, amount: usize) -> bool {
+    to_bottle.liquid_level == 0
+        && amount == from_bottle.liquid_level
+        && to_bottle.capacity() == from_bottle.capacity()
+}
+
+fn how_much_to_move(from_bottle: &Bottle, to_bottle: &Bottle) -> usize {
+    if !is_valid_to_move(from_bottle, to_bottle) {
         return 0;
     }
 
@@ -306,7 +1126,42 @@ fn how_much_to_move(from_bottle: &Bottle, to_bottle: &Bottle, levels: usize) ->
         }
     }
 
-    pourable_amount.min(levels - to_bottle.liquid_level)
+    pourable_amount.min(to_bottle.capacity() - to_bottle.liquid_level)
+}
+
+/// Largest first.
+fn legal_pour_amounts(state: &State, from: usize, to: usize) -> impl Iterator<Item = usize> {
+    let most = if state.lock(from) == Lock::Open && state.lock(to) == Lock::Open {
+        how_much_to_move(&state.state[from], &state.state[to])
+    } else {
+        0
+    };
+    let (low, high) = match state.rules().pour {
+        PourRule::Full => (most, most),
+        PourRule::Single => (1, most.min(1)),
+        PourRule::Partial => (1, most),
+    };
+    (low.max(1)..=high).rev()
+}
+
+fn pour_amounts(state: &State, from: usize, to: usize) -> impl Iterator<Item = usize> + '_ {
+    let (from_bottle, to_bottle) = (&state.state[from], &state.state[to]);
+    let skip_relabeling = state.settings.pruning.skip_relabeling_pours
+        && !matches!(state.rules().goal, GoalMode::Layout(_))
+        && !state
+            .locks
+            .iter()
+            .flatten()
+            .any(|lock| matches!(lock, Lock::Moves(_)));
+
+    legal_pour_amounts(state, from, to).filter(move |&amount| {
+        !(skip_relabeling && is_relabeling_pour(from_bottle, to_bottle, amount))
+    })
+}
+
+/// 0 when the solvers skip the pour.
+fn pour_amount(state: &State, from: usize, to: usize) -> usize {
+    pour_amounts(state, from, to).next().unwrap_or(0)
 }
 
 fn is_final_state_bottle(bottle: &Bottle) -> bool {
@@ -315,18 +1170,42 @@ fn is_final_state_bottle(bottle: &Bottle) -> bool {
     }
 
     let bottom_color = bottle.state[0];
-    if bottom_color == 0 {
+    if bottom_color == 0 || bottom_color == UNKNOWN_COLOR {
         return false;
     }
 
     (1..bottle.liquid_level).all(|k| bottle.state[k] == bottom_color)
 }
 
+fn matches_layout(bottle: &Bottle, target: &[u8]) -> bool {
+    let mut target_liquid = target.iter().rev().take_while(|&&color| color != 0);
+    bottle.state[0..bottle.liquid_level]
+        .iter()
+        .all(|color| target_liquid.next() == Some(color))
+        && target_liquid.next().is_none()
+}
+
 fn is_final_state_game(state: &State) -> bool {
-    (0..state.n_of_bottles).all(|i| is_final_state_bottle(&state.state[i]))
+    let bottles = &state.state[0..state.n_of_bottles];
+    match &state.rules().goal {
+        GoalMode::SingleColor => bottles.iter().all(is_final_state_bottle),
+        GoalMode::FullBottles => {
+            let mut seen_colors = HashSet::new();
+            bottles.iter().all(|bottle| {
+                bottle.liquid_level == 0
+                    || (bottle.liquid_level == bottle.capacity()
+                        && is_final_state_bottle(bottle)
+                        && seen_colors.insert(bottle.top_liquid))
+            })
+        }
+        GoalMode::Layout(layout) => bottles
+            .iter()
+            .zip(layout.iter())
+            .all(|(bottle, target)| matches_layout(bottle, target)),
+    }
 }
 
-fn move_liquid(from_bottle: &mut Bottle, to_bottle: &mut Bottle, amount: usize, levels: usize) {
+fn move_liquid(from_bottle: &mut Bottle, to_bottle: &mut Bottle, amount: usize) {
     if amount == 0 {
         return;
     }
@@ -343,8 +1222,8 @@ fn move_liquid(from_bottle: &mut Bottle, to_bottle: &mut Bottle, amount: usize,
         0
     };
 
-    for k in 0..amount {
-        if to_bottle.liquid_level < levels && to_bottle.liquid_level < MAX_LEVELS {
+    for _ in 0..amount {
+        if to_bottle.liquid_level < to_bottle.capacity() {
             to_bottle.state[to_bottle.liquid_level] = liquid_to_move;
             to_bottle.liquid_level += 1;
         } else {
@@ -356,139 +1235,67 @@ fn move_liquid(from_bottle: &mut Bottle, to_bottle: &mut Bottle, amount: usize,
 
 fn move_liquid_state(current_state: &State, from: usize, to: usize, amount: usize) -> State {
     let mut new_state = current_state.clone();
-    let game_levels = current_state.levels;
 
     if from < to {
         let (slice_before_to, slice_from_to) = new_state.state.split_at_mut(to);
-        move_liquid(
-            &mut slice_before_to[from],
-            &mut slice_from_to[0],
-            amount,
-            game_levels,
-        );
+        move_liquid(&mut slice_before_to[from], &mut slice_from_to[0], amount);
     } else {
         let (slice_before_from, slice_from_from) = new_state.state.split_at_mut(from);
-        move_liquid(
-            &mut slice_from_from[0],
-            &mut slice_before_from[to],
-            amount,
-            game_levels,
-        );
+        move_liquid(&mut slice_from_from[0], &mut slice_before_from[to], amount);
     }
 
-    new_state.previous_state = Some(Box::new(current_state.clone()));
+    advance_locks(&mut new_state);
+
+    new_state.previous_state = Some(Arc::new(current_state.clone()));
     new_state.last_movement = Some((from, to));
     new_state.how_much_moved = amount;
     new_state
 }
 
-fn calculate_heuristic(state: &State) -> f64 {
-    let mut score = 0.0;
-
-    let unfinished_bottles = state.state[0..state.n_of_bottles]
-        .iter()
-        .filter(|b| !is_final_state_bottle(b))
-        .count();
-    score += unfinished_bottles as f64;
-
-    let mut color_counts: HashMap<u8, Vec<(usize, usize)>> = HashMap::new();
-
-    for i in 0..state.n_of_bottles {
-        let bottle = &state.state[i];
-
-        if bottle.liquid_level == 0 {
-            continue;
-        }
-
-        for level in 0..bottle.liquid_level {
-            let color = bottle.state[level];
-            if color > 0 {
-                color_counts
-                    .entry(color)
-                    .or_insert_with(Vec::new)
-                    .push((i, level));
-            }
-        }
-    }
-
-    for (color, locations) in color_counts {
-        if color > 0 {
-            let mut bottles_with_this_color = HashSet::new();
-            for (bottle_idx, _) in &locations {
-                bottles_with_this_color.insert(bottle_idx);
-            }
-
-            if bottles_with_this_color.len() > 1 {
-                score += (bottles_with_this_color.len() - 1) as f64 * 0.8;
-            }
-
-            let total_units = locations.len();
-            let max_units_per_bottle = state.levels;
+fn advance_locks(state: &mut State) {
+    let Some(locks) = &mut state.locks else {
+        return;
+    };
 
-            if total_units > 0 && total_units <= max_units_per_bottle {
-                score += (bottles_with_this_color.len() - 1) as f64 * 0.5;
-            }
+    for lock in locks.iter_mut() {
+        if let Lock::Moves(moves) = lock {
+            *moves = moves.saturating_sub(1);
         }
     }
+    release_locks(state);
+}
 
-    for i in 0..state.n_of_bottles {
-        let bottle = &state.state[i];
-        if bottle.liquid_level <= 1 {
-            continue;
-        }
-
-        let mut transitions = 0;
-        for j in 1..bottle.liquid_level {
-            if bottle.state[j] != bottle.state[j - 1] {
-                transitions += 1;
-            }
-        }
-
-        score += transitions as f64 * 0.3;
+fn release_locks(state: &mut State) {
+    if !state.has_locks() {
+        return;
     }
 
-    for i in 0..state.n_of_bottles {
-        let bottle = &state.state[i];
-        if bottle.liquid_level == 0 || bottle.liquid_level == state.levels {
-            continue;
-        }
-
-        let mut is_single_color = true;
-        let first_color = bottle.state[0];
-
-        for j in 1..bottle.liquid_level {
-            if bottle.state[j] != first_color {
-                is_single_color = false;
-                break;
-            }
-        }
-
-        if is_single_color {
-            score -= 0.3;
+    let completed_colors: SmallVec<[u8; INLINE_BOTTLES]> = state.state[0..state.n_of_bottles]
+        .iter()
+        .filter(|bottle| {
+            bottle.liquid_level > 0
+                && bottle.liquid_level == bottle.capacity()
+                && is_final_state_bottle(bottle)
+        })
+        .map(|bottle| bottle.top_liquid)
+        .collect();
+
+    let Some(locks) = &mut state.locks else {
+        return;
+    };
+    for lock in locks.iter_mut() {
+        let unlocked = match *lock {
+            Lock::Open => false,
+            Lock::Color(color) => completed_colors.contains(&color),
+            Lock::Moves(moves) => moves == 0,
+        };
+        if unlocked {
+            *lock = Lock::Open;
         }
     }
-
-    if state.n_of_bottles >= 8 && state.levels >= 7 {
-        let partially_filled_bottles = state.state[0..state.n_of_bottles]
-            .iter()
-            .filter(|b| b.liquid_level > 0 && b.liquid_level < state.levels)
-            .count();
-
-        score += partially_filled_bottles as f64 * 0.2;
-
-        for i in 0..state.n_of_bottles {
-            let bottle = &state.state[i];
-            if bottle.liquid_level > 0 && bottle.liquid_level < state.levels {
-                let all_same = (1..bottle.liquid_level).all(|j| bottle.state[j] == bottle.state[0]);
-
-                if all_same {
-                    score -= 0.5;
-                }
-            }
-        }
+    if locks.iter().all(|&lock| lock == Lock::Open) {
+        state.locks = None;
     }
-
-    score
 }
 
 fn calculate_move_priority(state: &State, from: usize, to: usize, amount: usize) -> f64 {
@@ -508,7 +1315,7 @@ fn calculate_move_priority(state: &State, from: usize, to: usize, amount: usize)
         }
     }
 
-    if to_bottle.liquid_level + amount == state.levels {
+    if to_bottle.liquid_level + amount == to_bottle.capacity() {
         let potential_color = from_bottle.top_liquid;
         let mut would_complete = true;
 
@@ -555,11 +1362,11 @@ fn find_obvious_moves(state: &State) -> Option<(usize, usize, usize)> {
             }
 
             let to_bottle = &state.state[to];
-            let amount = how_much_to_move(from_bottle, to_bottle, state.levels);
+            let amount = pour_amount(state, from, to);
 
             if amount > 0 {
                 if to_bottle.liquid_level > 0
-                    && to_bottle.liquid_level + amount == state.levels
+                    && to_bottle.liquid_level + amount == to_bottle.capacity()
                     && (0..to_bottle.liquid_level)
                         .all(|i| to_bottle.state[i] == from_bottle.top_liquid)
                 {
@@ -605,7 +1412,7 @@ fn assign_empty_bottles_to_colors(state: &State) -> HashMap<usize, u8> {
     }
 
     let mut color_vec: Vec<(u8, usize)> = color_counts.into_iter().collect();
-    color_vec.sort_by(|a, b| b.1.cmp(&a.1));
+    color_vec.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
 
     for (idx, (color, _)) in color_vec.iter().enumerate() {
         if idx >= empty_bottles.len() {
@@ -642,10 +1449,11 @@ fn calculate_move_priority_with_assignments(
         }
     }
 
-    if to_bottle.liquid_level > 0 && from_bottle.liquid_level == amount {
-        if bottle_assignments.get(&from).is_none() {
-            priority += 1.0;
-        }
+    if to_bottle.liquid_level > 0
+        && from_bottle.liquid_level == amount
+        && !bottle_assignments.contains_key(&from)
+    {
+        priority += 1.0;
     }
 
     priority
@@ -664,8 +1472,7 @@ fn generate_sorted_moves(state: &State) -> Vec<(usize, usize, usize)> {
                 continue;
             }
 
-            let amount = how_much_to_move(&state.state[from], &state.state[to], state.levels);
-            if amount > 0 {
+            for amount in pour_amounts(state, from, to) {
                 let priority = calculate_move_priority(state, from, to, amount);
                 moves.push((from, to, amount, priority));
             }
@@ -694,9 +1501,7 @@ fn generate_sorted_moves_with_assignments(state: &State) -> Vec<(usize, usize, u
 
         for (&bottle_idx, &assigned_color) in &bottle_assignments {
             if assigned_color == from_color && state.state[bottle_idx].liquid_level == 0 {
-                let amount =
-                    how_much_to_move(&state.state[from], &state.state[bottle_idx], state.levels);
-                if amount > 0 {
+                for amount in pour_amounts(state, from, bottle_idx) {
                     moves.push((from, bottle_idx, amount, 10.0));
                 }
             }
@@ -713,8 +1518,7 @@ fn generate_sorted_moves_with_assignments(state: &State) -> Vec<(usize, usize, u
                 continue;
             }
 
-            let amount = how_much_to_move(&state.state[from], &state.state[to], state.levels);
-            if amount > 0 {
+            for amount in pour_amounts(state, from, to) {
                 let priority = calculate_move_priority_with_assignments(
                     state,
                     from,
@@ -756,13 +1560,7 @@ fn optimize_initial_distribution(initial_state: State) -> State {
                     continue;
                 }
 
-                let amount = how_much_to_move(
-                    &current_state.state[from],
-                    &current_state.state[to],
-                    current_state.levels,
-                );
-
-                if amount > 0 {
+                for amount in pour_amounts(&current_state, from, to) {
                     let priority = calculate_move_priority_with_assignments(
                         &current_state,
                         from,
@@ -800,11 +1598,15 @@ fn optimize_initial_distribution(initial_state: State) -> State {
     current_state
 }
 
-fn ida_star_search(initial_state: State) -> Option<State> {
-    let mut bound = calculate_heuristic(&initial_state);
+fn ida_star_search(
+    initial_state: State,
+    heuristic: &dyn Heuristic,
+    nodes_expanded: &mut usize,
+) -> Option<State> {
+    let mut bound = heuristic.estimate(&initial_state);
     let max_iterations = 100;
 
-    for iteration in 0..max_iterations {
+    for _ in 0..max_iterations {
         let mut path = Vec::new();
         path.push(initial_state.clone());
 
@@ -818,6 +1620,8 @@ fn ida_star_search(initial_state: State) -> Option<State> {
             0.0,
             bound,
             &mut transposition_table,
+            heuristic,
+            nodes_expanded,
         );
 
         if result.is_goal {
@@ -839,11 +1643,13 @@ fn ida_star_search_recursive(
     visited: &mut HashSet<State>,
     g: f64,
     bound: f64,
-    transposition_table: &mut HashMap<[Bottle; MAX_N_OF_BOTTLES], f64>,
+    transposition_table: &mut HashMap<Position, f64>,
+    heuristic: &dyn Heuristic,
+    nodes_expanded: &mut usize,
 ) -> SearchResult {
     let current = path.last().unwrap().clone();
 
-    let h = calculate_heuristic(&current);
+    let h = heuristic.estimate(&current);
     let f = g + h;
 
     if f > bound {
@@ -860,7 +1666,15 @@ fn ida_star_search_recursive(
         };
     }
 
-    if let Some(&previous_cost) = transposition_table.get(&current.state) {
+    if is_dead_state(&current) {
+        return SearchResult {
+            cost: f64::INFINITY,
+            is_goal: false,
+        };
+    }
+
+    let position = current.position();
+    if let Some(&previous_cost) = transposition_table.get(&position) {
         if previous_cost <= g {
             return SearchResult {
                 cost: f64::INFINITY,
@@ -869,7 +1683,12 @@ fn ida_star_search_recursive(
         }
     }
 
-    transposition_table.insert(current.state, g);
+    transposition_table.insert(position, g);
+    *nodes_expanded += 1;
+
+    // The obvious move's child is skipped by the transposition table in the
+    // loop below, so its cost has to count here or the next bound overshoots.
+    let mut min_cost = f64::INFINITY;
 
     if let Some((from, to, amount)) = find_obvious_moves(&current) {
         let next_state = move_liquid_state(&current, from, to, amount);
@@ -878,20 +1697,27 @@ fn ida_star_search_recursive(
             path.push(next_state.clone());
             visited.insert(next_state.clone());
 
-            let result =
-                ida_star_search_recursive(path, visited, g + 1.0, bound, transposition_table);
+            let result = ida_star_search_recursive(
+                path,
+                visited,
+                g + 1.0,
+                bound,
+                transposition_table,
+                heuristic,
+                nodes_expanded,
+            );
 
             if result.is_goal {
                 return result;
             }
 
+            min_cost = min_cost.min(result.cost);
+
             let last_state = path.pop().unwrap();
             visited.remove(&last_state);
         }
     }
 
-    let mut min_cost = f64::INFINITY;
-
     for (from, to, amount) in generate_sorted_moves_with_assignments(&current) {
         let next_state = move_liquid_state(&current, from, to, amount);
 
@@ -902,7 +1728,15 @@ fn ida_star_search_recursive(
         path.push(next_state.clone());
         visited.insert(next_state);
 
-        let result = ida_star_search_recursive(path, visited, g + 1.0, bound, transposition_table);
+        let result = ida_star_search_recursive(
+            path,
+            visited,
+            g + 1.0,
+            bound,
+            transposition_table,
+            heuristic,
+            nodes_expanded,
+        );
 
         if result.is_goal {
             return result;
@@ -920,10 +1754,10 @@ fn ida_star_search_recursive(
     }
 }
 
-fn enhanced_bfs_search(initial_state: State) -> Option<State> {
+fn enhanced_bfs_search(initial_state: State, nodes_expanded: &mut usize) -> Option<State> {
     let mut queue = VecDeque::new();
     let mut visited: HashSet<State> = HashSet::new();
-    let mut transposition_table: HashMap<[Bottle; MAX_N_OF_BOTTLES], usize> = HashMap::new();
+    let mut transposition_table: HashMap<Position, usize> = HashMap::new();
 
     visited.insert(initial_state.clone());
     queue.push_back(initial_state);
@@ -943,6 +1777,7 @@ fn enhanced_bfs_search(initial_state: State) -> Option<State> {
         if is_final_state_game(&current) {
             return Some(current);
         }
+        *nodes_expanded += 1;
 
         let current_heuristic = calculate_heuristic(&current);
         if current_heuristic < best_heuristic {
@@ -951,7 +1786,7 @@ fn enhanced_bfs_search(initial_state: State) -> Option<State> {
         }
 
         let current_depth = transposition_table
-            .get(&current.state)
+            .get(&current.position())
             .copied()
             .unwrap_or(0);
 
@@ -960,11 +1795,11 @@ fn enhanced_bfs_search(initial_state: State) -> Option<State> {
         for (from, to, amount) in generate_sorted_moves_with_assignments(&current) {
             let next_state = move_liquid_state(&current, from, to, amount);
 
-            if !visited.insert(next_state.clone()) {
+            if !visited.insert(next_state.clone()) || is_dead_state(&next_state) {
                 continue;
             }
 
-            transposition_table.insert(next_state.state, current_depth + 1);
+            transposition_table.insert(next_state.position(), current_depth + 1);
 
             let next_h = calculate_heuristic(&next_state);
             future_states.push((next_state, next_h));
@@ -992,13 +1827,18 @@ fn enhanced_bfs_search(initial_state: State) -> Option<State> {
     best_seen_state
 }
 
-fn beam_search(initial_state: State, beam_width: usize) -> Option<State> {
+fn beam_search(
+    initial_state: State,
+    beam_width: usize,
+    deadline: f64,
+    nodes_expanded: &mut usize,
+) -> Option<State> {
     let mut beam = vec![initial_state];
     let mut visited = HashSet::new();
     let max_iterations = 10000;
 
     for _ in 0..max_iterations {
-        if beam.is_empty() {
+        if beam.is_empty() || now_ms() > deadline {
             return None;
         }
 
@@ -1011,11 +1851,18 @@ fn beam_search(initial_state: State, beam_width: usize) -> Option<State> {
         let mut all_successors = Vec::new();
 
         for state in beam {
+            if deadline.is_finite() && now_ms() > deadline {
+                return None;
+            }
+            *nodes_expanded += 1;
             for (from, to, amount) in generate_sorted_moves_with_assignments(&state) {
                 let successor = move_liquid_state(&state, from, to, amount);
 
                 if !visited.contains(&successor) {
                     visited.insert(successor.clone());
+                    if is_dead_state(&successor) {
+                        continue;
+                    }
                     let h = calculate_heuristic(&successor);
                     all_successors.push((successor, h));
                 }
@@ -1034,9 +1881,22 @@ fn beam_search(initial_state: State, beam_width: usize) -> Option<State> {
     None
 }
 
+#[cfg(target_arch = "wasm32")]
+fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_ms() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs_f64() * 1000.0)
+        .unwrap_or(0.0)
+}
+
 fn extract_movements(mut solution: State) -> Vec<Movement> {
     let mut movements = VecDeque::new();
-    while let Some(prev_box) = solution.previous_state {
+    while let Some(previous) = solution.previous_state {
         if let Some((from, to)) = solution.last_movement {
             movements.push_front(Movement {
                 from,
@@ -1044,11 +1904,244 @@ fn extract_movements(mut solution: State) -> Vec<Movement> {
                 amount: solution.how_much_moved,
             });
         }
-        solution = *prev_box;
+        solution = Arc::unwrap_or_clone(previous);
     }
     movements.into()
 }
 
-fn bfs_liquid_pouring(initial_state: State) -> Option<State> {
-    enhanced_bfs_search(initial_state)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristics::LowerBound;
+    use crate::test_support::{board, exact_distances, locked_board, solved_boards};
+
+    #[test]
+    fn optimal_search_matches_breadth_first_search() {
+        for (start, distances) in solved_boards(11, 25, &Rules::default()) {
+            let optimum = distances.get(&start.state).copied();
+            let found = ida_star_search(start.clone(), &LowerBound, &mut 0)
+                .map(|solution| extract_movements(solution).len());
+            assert_eq!(
+                found,
+                optimum,
+                "{:?} under {:?}",
+                start.state,
+                start.rules()
+            );
+        }
+    }
+
+    #[test]
+    fn optimal_search_counts_the_obvious_move_towards_the_next_bound() {
+        let rules = Rules {
+            pour: PourRule::Single,
+            ..Rules::default()
+        };
+        let start = board(3, &[&[2, 1, 3], &[1, 3, 1], &[2, 2, 3], &[0, 0, 0]], rules);
+
+        let solution = ida_star_search(start, &admissible_heuristic(), &mut 0).unwrap();
+        assert_eq!(extract_movements(solution).len(), 7);
+    }
+
+    #[test]
+    fn every_strategy_reports_its_expansions() {
+        let start = board(
+            3,
+            &[&[2, 1, 3], &[1, 3, 1], &[2, 2, 3], &[0, 0, 0], &[0, 0, 0]],
+            Rules::default(),
+        );
+        for strategy in [
+            Strategy::IdaStar,
+            Strategy::EnhancedBfs,
+            Strategy::Bidirectional,
+            Strategy::Beam(100),
+            Strategy::WeightedAStar(1.5),
+            Strategy::Mcts(1000),
+        ] {
+            let (solution, stats) = run_strategy(strategy, start.clone());
+            assert!(solution.is_some(), "{}", stats.strategy);
+            assert!(
+                stats.nodes_expanded.is_some_and(|n| n > 0),
+                "{}",
+                stats.strategy
+            );
+        }
+    }
+
+    #[test]
+    fn strategy_parameters_are_validated_when_parsed() {
+        assert!(matches!(Strategy::parse("beam:5"), Ok(Strategy::Beam(5))));
+        assert!(matches!(
+            Strategy::parse("weighted-astar"),
+            Ok(Strategy::WeightedAStar(weight)) if weight == 2.0
+        ));
+        for text in [
+            "beam:0",
+            "mcts:0",
+            "weighted-astar:NaN",
+            "weighted-astar:inf",
+            "weighted-astar:-3",
+            "weighted-astar:0.5",
+            "beam:wide",
+            "greedy",
+        ] {
+            assert!(Strategy::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn states_partway_through_a_cached_solution_hit_the_cache() {
+        let start = board(
+            3,
+            &[&[2, 1, 3], &[1, 3, 1], &[2, 2, 3], &[0, 0, 0], &[0, 0, 0]],
+            Rules::default(),
+        );
+        let solved = solve_state(&start).unwrap();
+        assert_ne!(solved.stats.strategy, "cache");
+        assert!(solved.movements.len() > 2);
+
+        let midway = optimizer::replay(&start, &solved.movements[..2])
+            .unwrap()
+            .pop()
+            .unwrap();
+        let outcome = solve_state(&midway).unwrap();
+        assert_eq!(outcome.stats.strategy, "cache");
+        assert_eq!(outcome.movements, solved.movements[2..]);
+    }
+
+    #[test]
+    fn move_priority_rewards_filling_a_small_bottle() {
+        let game_state = JsGameState {
+            bottles: vec![vec![0, 0, 0, 1], vec![0, 0, 0, 1], vec![0, 0, 0, 0]],
+            capacities: Some(vec![2, 4, 4]),
+            locks: None,
+            rules: Rules::default(),
+            pruning: Pruning::default(),
+        };
+        let state = convert_to_internal_state(&game_state, 4, 3).unwrap();
+
+        assert!(
+            calculate_move_priority(&state, 1, 0, 1) > calculate_move_priority(&state, 0, 1, 1)
+        );
+    }
+
+    #[test]
+    fn solves_bottles_taller_than_the_inline_cells() {
+        let levels = INLINE_LEVELS + 4;
+        let mixed: Vec<u8> = (0..levels).map(|i| 1 + (i / 3 % 2) as u8).collect();
+        let mut swapped = mixed.clone();
+        swapped.iter_mut().for_each(|color| *color = 3 - *color);
+        let start = board(
+            levels,
+            &[&mixed, &swapped, &vec![0; levels]],
+            Rules::default(),
+        );
+        assert!(start.state[0].state.spilled());
+
+        let outcome = solve_state(&start).unwrap();
+        assert!(outcome.stats.solved);
+        assert!(optimizer::verify_movements(&start, &outcome.movements));
+    }
+
+    #[test]
+    fn color_lock_opens_once_a_bottle_fills_with_its_color() {
+        let start = locked_board(
+            2,
+            &[&[0, 1], &[0, 1], &[2, 2], &[0, 0]],
+            &[Lock::Open, Lock::Open, Lock::Open, Lock::Color(1)],
+        );
+        assert_eq!(start.lock(3), Lock::Color(1));
+        assert_eq!(legal_pour_amounts(&start, 0, 3).next(), None);
+
+        let next_state = move_liquid_state(&start, 0, 1, 1);
+        assert_eq!(next_state.lock(3), Lock::Open);
+        assert!(!next_state.has_locks());
+    }
+
+    #[test]
+    fn move_lock_opens_after_its_moves() {
+        let start = locked_board(
+            2,
+            &[&[2, 1], &[1, 2], &[0, 0], &[0, 0]],
+            &[Lock::Open, Lock::Open, Lock::Moves(2), Lock::Open],
+        );
+
+        let next_state = move_liquid_state(&start, 0, 3, 1);
+        assert_eq!(next_state.lock(2), Lock::Moves(1));
+        assert_eq!(legal_pour_amounts(&next_state, 1, 2).next(), None);
+
+        let next_state = move_liquid_state(&next_state, 1, 0, 1);
+        assert_eq!(next_state.lock(2), Lock::Open);
+        assert_eq!(legal_pour_amounts(&next_state, 3, 2).next(), Some(1));
+    }
+
+    #[test]
+    fn solves_puzzles_that_wait_out_a_move_lock() {
+        // The only pour at first swaps two bottles, which default pruning skips.
+        let start = locked_board(
+            2,
+            &[&[2, 1], &[1, 2], &[3, 3], &[0, 0]],
+            &[Lock::Moves(1), Lock::Moves(1), Lock::Open, Lock::Open],
+        );
+        assert!(!analysis::is_dead_end(&start, DEAD_END_SEARCH_LIMIT));
+
+        let outcome = solve_state(&start).unwrap();
+        assert_eq!(outcome.movements.len(), 3);
+        assert!(optimizer::verify_movements(&start, &outcome.movements));
+    }
+
+    #[test]
+    fn solvers_make_relabeling_pours_once_pruning_is_off() {
+        let mut start = board(2, &[&[0, 1], &[2, 1], &[0, 2], &[0, 0]], Rules::default());
+        let relabels = |state: &State| {
+            generate_sorted_moves_with_assignments(state)
+                .iter()
+                .any(|&(from, to, _)| (from, to) == (0, 3))
+        };
+        assert_eq!(legal_pour_amounts(&start, 0, 3).next(), Some(1));
+        assert!(!relabels(&start));
+
+        start.settings = Arc::new(Settings {
+            pruning: Pruning {
+                skip_relabeling_pours: false,
+            },
+            ..Settings::default()
+        });
+        assert!(relabels(&start));
+        for strategy in [
+            Strategy::IdaStar,
+            Strategy::EnhancedBfs,
+            Strategy::Bidirectional,
+            Strategy::Beam(100),
+            Strategy::WeightedAStar(1.5),
+            Strategy::Mcts(1000),
+        ] {
+            let (solution, stats) = run_strategy(strategy, start.clone());
+            let movements = extract_movements(solution.unwrap());
+            assert!(
+                optimizer::verify_movements(&start, &movements),
+                "{}",
+                stats.strategy
+            );
+        }
+    }
+
+    #[test]
+    fn solves_towards_goal_layouts_that_mix_colors() {
+        let layout = vec![vec![0, 3, 3], vec![2, 2, 3], vec![0, 0, 2], vec![1, 1, 1]];
+        let start = board(
+            3,
+            &[&[2, 1, 1], &[1, 2, 3], &[3, 3, 2], &[0, 0, 0]],
+            Rules {
+                goal: GoalMode::Layout(Arc::new(layout)),
+                ..Rules::default()
+            },
+        );
+        let optimum = exact_distances(&start)[&start.state];
+
+        let outcome = solve_state(&start).unwrap();
+        assert!(optimizer::verify_movements(&start, &outcome.movements));
+        let solution = ida_star_search(start, &admissible_heuristic(), &mut 0).unwrap();
+        assert_eq!(extract_movements(solution).len(), optimum);
+    }
 }
@@ -0,0 +1,207 @@
+use crate::analysis::is_dead_state;
+use crate::heuristics::calculate_heuristic;
+use crate::{generate_sorted_moves, is_final_state_game, move_liquid_state, State};
+use std::collections::HashSet;
+
+type Move = (usize, usize, usize);
+
+pub(crate) struct MctsConfig {
+    pub(crate) iterations: usize,
+    pub(crate) max_rollout_depth: usize,
+    pub(crate) exploration: f64,
+    pub(crate) seed: u64,
+}
+
+impl MctsConfig {
+    pub(crate) fn new(iterations: usize) -> Self {
+        Self {
+            iterations,
+            max_rollout_depth: 200,
+            exploration: std::f64::consts::SQRT_2,
+            seed: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+}
+
+/// Xorshift generator.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed | 1)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Takes each move of a best-first list with probability one half.
+    fn pick_biased(&mut self, len: usize) -> usize {
+        let mut index = 0;
+        while index + 1 < len && self.next_f64() < 0.5 {
+            index += 1;
+        }
+        index
+    }
+}
+
+struct Node {
+    state: State,
+    parent: Option<usize>,
+    movement: Option<Move>,
+    children: Vec<usize>,
+    untried: Vec<Move>,
+    visits: f64,
+    total_reward: f64,
+}
+
+impl Node {
+    fn new(mut state: State, parent: Option<usize>, movement: Option<Move>) -> Self {
+        state.previous_state = None;
+        let mut untried = generate_sorted_moves(&state);
+        // Expansion pops from the back, so keep the best move last.
+        untried.reverse();
+        Self {
+            state,
+            parent,
+            movement,
+            children: Vec::new(),
+            untried,
+            visits: 0.0,
+            total_reward: 0.0,
+        }
+    }
+}
+
+fn path_to(tree: &[Node], mut index: usize) -> Vec<Move> {
+    let mut moves = Vec::new();
+    while let Some(movement) = tree[index].movement {
+        moves.push(movement);
+        index = tree[index].parent.unwrap();
+    }
+    moves.reverse();
+    moves
+}
+
+fn rollout(state: &State, config: &MctsConfig, rng: &mut Rng) -> (f64, Option<Vec<Move>>) {
+    let mut current = state.clone();
+    let mut seen = HashSet::new();
+    let mut moves = Vec::new();
+    seen.insert(current.clone());
+
+    for _ in 0..config.max_rollout_depth {
+        if is_final_state_game(&current) {
+            let reward = 1.0 + 1.0 / (1.0 + moves.len() as f64);
+            return (reward, Some(moves));
+        }
+
+        let candidates: Vec<_> = generate_sorted_moves(&current)
+            .into_iter()
+            .filter(|&(from, to, amount)| {
+                let mut next_state = move_liquid_state(&current, from, to, amount);
+                next_state.previous_state = None;
+                !seen.contains(&next_state)
+            })
+            .collect();
+        if candidates.is_empty() {
+            break;
+        }
+
+        let (from, to, amount) = candidates[rng.pick_biased(candidates.len())];
+        current = move_liquid_state(&current, from, to, amount);
+        current.previous_state = None;
+        seen.insert(current.clone());
+        moves.push((from, to, amount));
+    }
+
+    (1.0 / (1.0 + calculate_heuristic(&current).max(0.0)), None)
+}
+
+/// Returns the shortest solution seen in the tree or in any playout.
+pub(crate) fn mcts_search(
+    initial_state: State,
+    config: &MctsConfig,
+    nodes_expanded: &mut usize,
+) -> Option<State> {
+    if is_final_state_game(&initial_state) {
+        return Some(initial_state);
+    }
+
+    let mut rng = Rng::new(config.seed);
+    let mut tree = vec![Node::new(initial_state.clone(), None, None)];
+    let mut in_tree = HashSet::new();
+    in_tree.insert(tree[0].state.clone());
+
+    let mut best: Option<Vec<Move>> = None;
+
+    for _ in 0..config.iterations {
+        let mut index = 0;
+        while tree[index].untried.is_empty() && !tree[index].children.is_empty() {
+            let parent_visits = tree[index].visits.max(1.0).ln();
+            index = *tree[index]
+                .children
+                .iter()
+                .max_by(|&&a, &&b| {
+                    let score = |child: &Node| {
+                        child.total_reward / child.visits.max(1.0)
+                            + config.exploration * (parent_visits / child.visits.max(1.0)).sqrt()
+                    };
+                    score(&tree[a])
+                        .partial_cmp(&score(&tree[b]))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .unwrap();
+        }
+
+        while let Some((from, to, amount)) = tree[index].untried.pop() {
+            let mut next_state = move_liquid_state(&tree[index].state, from, to, amount);
+            next_state.previous_state = None;
+            if is_dead_state(&next_state) || !in_tree.insert(next_state.clone()) {
+                continue;
+            }
+
+            *nodes_expanded += 1;
+            tree.push(Node::new(next_state, Some(index), Some((from, to, amount))));
+            let child = tree.len() - 1;
+            tree[index].children.push(child);
+            index = child;
+            break;
+        }
+
+        let (reward, playout) = if is_final_state_game(&tree[index].state) {
+            (2.0, Some(Vec::new()))
+        } else {
+            rollout(&tree[index].state, config, &mut rng)
+        };
+
+        if let Some(playout) = playout {
+            let mut moves = path_to(&tree, index);
+            moves.extend(playout);
+            if best.as_ref().is_none_or(|best| moves.len() < best.len()) {
+                best = Some(moves);
+            }
+        }
+
+        let mut current = Some(index);
+        while let Some(node) = current {
+            tree[node].visits += 1.0;
+            tree[node].total_reward += reward;
+            current = tree[node].parent;
+        }
+    }
+
+    best.map(|moves| {
+        moves
+            .into_iter()
+            .fold(initial_state, |state, (from, to, amount)| {
+                move_liquid_state(&state, from, to, amount)
+            })
+    })
+}
@@ -0,0 +1,168 @@
+use crate::{
+    convert_to_internal_state, extract_movements, run_strategy, solve_state, JsGameState, Lock,
+    Movement, Pruning, Rules, SearchStats, State, Strategy, StrategyOutcome,
+};
+use serde::{Deserialize, Serialize};
+
+/// Without a `strategy` the full `solve` pipeline runs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SolveRequest {
+    pub id: u32,
+    pub levels: usize,
+    pub n_of_bottles: usize,
+    pub bottles: Vec<Vec<u8>>,
+    /// Per-bottle capacities; every bottle holds `levels` units when absent.
+    #[serde(default)]
+    pub capacities: Option<Vec<usize>>,
+    /// Per-bottle locks; every bottle is open when absent.
+    #[serde(default)]
+    pub locks: Option<Vec<Lock>>,
+    #[serde(default)]
+    pub rules: Rules,
+    #[serde(default)]
+    pub pruning: Pruning,
+    #[serde(default)]
+    pub strategy: Option<String>,
+}
+
+/// Exactly one of `stats` and `error` is set.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SolveResponse {
+    pub id: u32,
+    pub movements: Vec<Movement>,
+    pub stats: Option<SearchStats>,
+    pub error: Option<String>,
+}
+
+impl SolveRequest {
+    pub(crate) fn initial_state(&self) -> Result<State, String> {
+        convert_to_internal_state(
+            &JsGameState {
+                bottles: self.bottles.clone(),
+                capacities: self.capacities.clone(),
+                locks: self.locks.clone(),
+                rules: self.rules.clone(),
+                pruning: self.pruning,
+            },
+            self.levels,
+            self.n_of_bottles,
+        )
+    }
+}
+
+fn solve_request(request: SolveRequest) -> Result<StrategyOutcome, String> {
+    let initial_state = request.initial_state()?;
+
+    match request.strategy {
+        None => solve_state(&initial_state),
+        Some(strategy) => {
+            let max_moves = initial_state.rules().max_moves;
+            let (solution, stats) = run_strategy(Strategy::parse(&strategy)?, initial_state);
+            let solution = solution.ok_or_else(|| "No solution found".to_string())?;
+            let movements = extract_movements(solution);
+            if let Some(max_moves) = max_moves {
+                if movements.len() > max_moves {
+                    return Err(format!(
+                        "Solution of {} moves exceeds the limit of {} moves",
+                        movements.len(),
+                        max_moves
+                    ));
+                }
+            }
+            Ok(StrategyOutcome { movements, stats })
+        }
+    }
+}
+
+pub(crate) fn handle_request(request: SolveRequest) -> SolveResponse {
+    let id = request.id;
+    match solve_request(request) {
+        Ok(outcome) => SolveResponse {
+            id,
+            movements: outcome.movements,
+            stats: Some(outcome.stats),
+            error: None,
+        },
+        Err(error) => SolveResponse {
+            id,
+            movements: Vec::new(),
+            stats: None,
+            error: Some(error),
+        },
+    }
+}
+
+/// Answers every puzzle, failures included, in order.
+pub fn solve_batch(puzzles: Vec<SolveRequest>) -> Vec<SolveResponse> {
+    puzzles.into_iter().map(handle_request).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::verify_movements;
+
+    fn request(id: u32, max_moves: Option<usize>, strategy: Option<&str>) -> SolveRequest {
+        SolveRequest {
+            id,
+            levels: 2,
+            n_of_bottles: 3,
+            bottles: vec![vec![2, 1], vec![1, 2], vec![0, 0]],
+            capacities: None,
+            locks: None,
+            rules: Rules {
+                max_moves,
+                ..Rules::default()
+            },
+            pruning: Pruning::default(),
+            strategy: strategy.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn responses_echo_the_request_id() {
+        let request = request(7, None, None);
+        let start = request.initial_state().unwrap();
+
+        let response = handle_request(request);
+        assert_eq!(response.id, 7);
+        assert!(response.error.is_none());
+        assert!(response.stats.is_some());
+        assert!(verify_movements(&start, &response.movements));
+    }
+
+    #[test]
+    fn failed_responses_carry_only_the_error() {
+        let response = handle_request(request(9, None, Some("nope")));
+        assert_eq!(response.id, 9);
+        assert_eq!(response.error.as_deref(), Some("Unknown strategy: nope"));
+        assert!(response.stats.is_none());
+        assert!(response.movements.is_empty());
+    }
+
+    #[test]
+    fn strategy_solutions_may_not_exceed_the_move_limit() {
+        let response = handle_request(request(0, Some(1), Some("bidirectional")));
+        assert_eq!(
+            response.error.as_deref(),
+            Some("Solution of 2 moves exceeds the limit of 1 moves")
+        );
+        assert!(response.stats.is_none());
+    }
+
+    #[test]
+    fn a_failing_puzzle_does_not_stop_the_batch() {
+        let responses = solve_batch(vec![
+            request(1, None, None),
+            request(2, None, Some("nope")),
+            request(3, None, None),
+        ]);
+
+        let ids: Vec<u32> = responses.iter().map(|response| response.id).collect();
+        assert_eq!(ids, [1, 2, 3]);
+        assert!(responses[0].error.is_none());
+        assert!(responses[1].error.is_some());
+        assert!(responses[2].error.is_none());
+        assert!(!responses[2].movements.is_empty());
+    }
+}
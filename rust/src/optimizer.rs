@@ -0,0 +1,382 @@
+use crate::{
+    extract_movements, is_final_state_game, move_liquid_state, now_ms, pour_amount, pour_amounts,
+    Movement, State,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+const SHORTCUT_MAX_DEPTH: usize = 6;
+const SHORTCUT_MAX_NODES: usize = 5000;
+
+/// `None` unless the rules let the pour move exactly its amount.
+pub(crate) fn apply_movement(state: &State, movement: &Movement) -> Option<State> {
+    let Movement { from, to, amount } = *movement;
+    if from == to || from >= state.n_of_bottles || to >= state.n_of_bottles {
+        return None;
+    }
+
+    if !pour_amounts(state, from, to).any(|legal| legal == amount) {
+        return None;
+    }
+
+    let mut next_state = move_liquid_state(state, from, to, amount);
+    next_state.previous_state = None;
+    Some(next_state)
+}
+
+/// Every state visited by `movements`, starting with `initial`.
+pub(crate) fn replay(initial: &State, movements: &[Movement]) -> Option<Vec<State>> {
+    let mut current = initial.clone();
+    current.previous_state = None;
+
+    let mut states = vec![current];
+    for movement in movements {
+        let next_state = apply_movement(states.last().unwrap(), movement)?;
+        states.push(next_state);
+    }
+    Some(states)
+}
+
+pub(crate) fn verify_movements(initial: &State, movements: &[Movement]) -> bool {
+    replay(initial, movements).is_some_and(|states| is_final_state_game(states.last().unwrap()))
+}
+
+/// Recomputes each amount as the largest the rules allow.
+fn replay_pours(initial: &State, pours: &[(usize, usize)]) -> Option<Vec<Movement>> {
+    let mut current = initial.clone();
+    let mut movements = Vec::with_capacity(pours.len());
+
+    for &(from, to) in pours {
+        let amount = pour_amount(&current, from, to);
+        let movement = Movement { from, to, amount };
+        current = apply_movement(&current, &movement)?;
+        movements.push(movement);
+    }
+
+    is_final_state_game(&current).then_some(movements)
+}
+
+fn single_move_between(from_state: &State, to_state: &State) -> Option<Movement> {
+    for from in 0..from_state.n_of_bottles {
+        for to in 0..from_state.n_of_bottles {
+            if from == to {
+                continue;
+            }
+
+            for amount in pour_amounts(from_state, from, to) {
+                let movement = Movement { from, to, amount };
+                if apply_movement(from_state, &movement).is_some_and(|state| state == *to_state) {
+                    return Some(movement);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Jumps from each state to the last time the path visits it.
+fn remove_cycles(states: &[State], movements: &[Movement]) -> Vec<Movement> {
+    let mut last_seen = HashMap::new();
+    for (i, state) in states.iter().enumerate() {
+        last_seen.insert(state, i);
+    }
+
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < movements.len() && !is_final_state_game(&states[i]) {
+        i = last_seen[&states[i]];
+        if i < movements.len() {
+            result.push(movements[i].clone());
+            i += 1;
+        }
+    }
+    result
+}
+
+/// Replaces `A -> B, B -> C` with `A -> C` when it reaches the same state.
+fn merge_pours(states: &[State], movements: &[Movement]) -> Vec<Movement> {
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < movements.len() {
+        if i + 2 < states.len() {
+            if let Some(merged) = single_move_between(&states[i], &states[i + 2]) {
+                result.push(merged);
+                i += 2;
+                continue;
+            }
+        }
+        result.push(movements[i].clone());
+        i += 1;
+    }
+    result
+}
+
+fn remove_redundant_pours(initial: &State, movements: &[Movement]) -> Vec<Movement> {
+    let mut pours: Vec<(usize, usize)> = movements.iter().map(|m| (m.from, m.to)).collect();
+    let mut result = movements.to_vec();
+
+    let mut i = 0;
+    while i < pours.len() {
+        let mut candidate = pours.clone();
+        candidate.remove(i);
+
+        if let Some(shorter) = replay_pours(initial, &candidate) {
+            pours = candidate;
+            result = shorter;
+        } else {
+            i += 1;
+        }
+    }
+    result
+}
+
+/// Repeats the cleanups until nothing changes. An invalid solution is
+/// returned as is.
+pub(crate) fn optimize_movements(initial: &State, movements: Vec<Movement>) -> Vec<Movement> {
+    if !verify_movements(initial, &movements) {
+        return movements;
+    }
+
+    let mut best = movements;
+    loop {
+        let states = replay(initial, &best).unwrap();
+        let mut candidate = remove_cycles(&states, &best);
+
+        let states = replay(initial, &candidate).unwrap();
+        candidate = merge_pours(&states, &candidate);
+        candidate = remove_redundant_pours(initial, &candidate);
+
+        if candidate.len() >= best.len() || !verify_movements(initial, &candidate) {
+            return best;
+        }
+        best = candidate;
+    }
+}
+
+/// A shorter way from `states[start]` to a later state of the path or to any
+/// goal: the index it rejoins at and its moves.
+fn find_shortcut(
+    states: &[State],
+    path_index: &HashMap<&State, usize>,
+    start: usize,
+    max_depth: usize,
+    max_nodes: usize,
+) -> Option<(usize, Vec<Movement>)> {
+    let goal_index = states.len() - 1;
+    let mut best: Option<(usize, State)> = None;
+    let mut best_saving = 0;
+
+    let mut origin = states[start].clone();
+    origin.previous_state = None;
+
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert(origin.clone());
+    queue.push_back((origin, 0));
+
+    while let Some((current, depth)) = queue.pop_front() {
+        if depth >= max_depth || visited.len() > max_nodes {
+            break;
+        }
+
+        for from in 0..current.n_of_bottles {
+            for to in 0..current.n_of_bottles {
+                if from == to {
+                    continue;
+                }
+
+                for amount in pour_amounts(&current, from, to) {
+                    let next_state = move_liquid_state(&current, from, to, amount);
+                    if !visited.insert(next_state.clone()) {
+                        continue;
+                    }
+
+                    let rejoin = if is_final_state_game(&next_state) {
+                        Some(goal_index)
+                    } else {
+                        path_index.get(&next_state).copied()
+                    };
+
+                    if let Some(index) = rejoin.filter(|&index| index > start) {
+                        let saving = (index - start).saturating_sub(depth + 1);
+                        if saving > best_saving {
+                            best_saving = saving;
+                            best = Some((index, next_state.clone()));
+                        }
+                    }
+
+                    queue.push_back((next_state, depth + 1));
+                }
+            }
+        }
+    }
+
+    best.map(|(index, state)| (index, extract_movements(state)))
+}
+
+/// Splices in shortcuts until there are none left, with `optimize_movements`
+/// before and after.
+pub(crate) fn shorten_movements(initial: &State, movements: Vec<Movement>) -> Vec<Movement> {
+    shorten_movements_until(initial, movements, f64::INFINITY)
+}
+
+/// Like `shorten_movements`, but stops looking for shortcuts at `deadline`
+/// (in `now_ms` milliseconds).
+pub(crate) fn shorten_movements_until(
+    initial: &State,
+    movements: Vec<Movement>,
+    deadline: f64,
+) -> Vec<Movement> {
+    if now_ms() >= deadline {
+        return movements;
+    }
+
+    let mut best = optimize_movements(initial, movements);
+    if !verify_movements(initial, &best) {
+        return best;
+    }
+
+    let mut start = 0;
+    while now_ms() < deadline {
+        let states = replay(initial, &best).unwrap();
+        let mut path_index = HashMap::new();
+        for (i, state) in states.iter().enumerate() {
+            path_index.insert(state, i);
+        }
+
+        let shortcut = (start..states.len().saturating_sub(1))
+            .take_while(|_| now_ms() < deadline)
+            .find_map(|i| {
+                find_shortcut(
+                    &states,
+                    &path_index,
+                    i,
+                    SHORTCUT_MAX_DEPTH,
+                    SHORTCUT_MAX_NODES,
+                )
+                .map(|(rejoin, shortcut)| (i, rejoin, shortcut))
+            });
+
+        let Some((i, rejoin, shortcut)) = shortcut else {
+            break;
+        };
+
+        let mut candidate = best[..i].to_vec();
+        candidate.extend(shortcut);
+        if rejoin < best.len() {
+            candidate.extend_from_slice(&best[rejoin..]);
+        }
+
+        if candidate.len() >= best.len() || !verify_movements(initial, &candidate) {
+            break;
+        }
+        best = candidate;
+        start = i;
+    }
+
+    if now_ms() < deadline {
+        optimize_movements(initial, best)
+    } else {
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristics::LowerBound;
+    use crate::ida_star_search;
+    use crate::mcts::Rng;
+    use crate::test_support::{exact_distances, random_boards};
+    use crate::Rules;
+
+    /// A solution that wanders for up to `steps` random moves before solving.
+    fn padded_solution(start: &State, rng: &mut Rng, steps: usize) -> Option<Vec<Movement>> {
+        let mut current = start.clone();
+        let mut movements = Vec::new();
+        for _ in 0..steps {
+            let moves: Vec<Movement> = (0..current.n_of_bottles)
+                .flat_map(|from| (0..current.n_of_bottles).map(move |to| (from, to)))
+                .filter(|&(from, to)| from != to)
+                .flat_map(|(from, to)| {
+                    pour_amounts(&current, from, to).map(move |amount| Movement {
+                        from,
+                        to,
+                        amount,
+                    })
+                })
+                .collect();
+            if moves.is_empty() {
+                break;
+            }
+            let movement = moves[(rng.next_u64() % moves.len() as u64) as usize].clone();
+            current = apply_movement(&current, &movement).unwrap();
+            movements.push(movement);
+        }
+
+        movements.extend(extract_movements(ida_star_search(
+            current,
+            &LowerBound,
+            &mut 0,
+        )?));
+        Some(movements)
+    }
+
+    #[test]
+    fn optimized_solutions_stay_valid_and_never_grow() {
+        let mut rng = Rng::new(19);
+        let mut saved = 0;
+        for start in random_boards(19, 30, &Rules::default()) {
+            let Some(movements) = padded_solution(&start, &mut rng, 12) else {
+                continue;
+            };
+            assert!(verify_movements(&start, &movements));
+
+            let optimized = optimize_movements(&start, movements.clone());
+            assert!(verify_movements(&start, &optimized));
+            assert!(optimized.len() <= movements.len());
+            saved += movements.len() - optimized.len();
+        }
+        assert!(saved > 0);
+    }
+
+    #[test]
+    fn shortened_solutions_are_valid_and_no_shorter_than_optimal() {
+        let mut rng = Rng::new(29);
+        for start in random_boards(29, 30, &Rules::default()) {
+            let Some(movements) = padded_solution(&start, &mut rng, 20) else {
+                continue;
+            };
+            let optimum = exact_distances(&start)[&start.state];
+
+            let optimized = optimize_movements(&start, movements.clone());
+            let shortened = shorten_movements(&start, movements);
+            assert!(verify_movements(&start, &shortened));
+            assert!(shortened.len() <= optimized.len());
+            assert!(shortened.len() >= optimum);
+        }
+    }
+
+    #[test]
+    fn shortening_stops_at_the_deadline() {
+        let mut rng = Rng::new(29);
+        for start in random_boards(29, 10, &Rules::default()) {
+            let Some(movements) = padded_solution(&start, &mut rng, 20) else {
+                continue;
+            };
+            let shortened = shorten_movements_until(&start, movements.clone(), now_ms() - 1.0);
+            assert_eq!(shortened, movements);
+        }
+    }
+
+    #[test]
+    fn invalid_solutions_are_returned_unchanged() {
+        let start = &random_boards(23, 1, &Rules::default())[0];
+        let movements = vec![Movement {
+            from: 0,
+            to: 0,
+            amount: 1,
+        }];
+
+        assert_eq!(optimize_movements(start, movements.clone()), movements);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::analysis::is_dead_state;
+use crate::heuristics::{Greedy, Heuristic};
+use crate::optimizer::shorten_movements;
+use crate::{
+    extract_movements, generate_sorted_moves_with_assignments, ida_star_search_recursive,
+    is_final_state_game, move_liquid_state, solve_state, Movement, SolveRequest, State,
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Cuts off every node once `solved` is set, so the other workers unwind.
+struct UntilSolved<'a> {
+    heuristic: &'a dyn Heuristic,
+    solved: &'a AtomicBool,
+}
+
+impl Heuristic for UntilSolved<'_> {
+    fn name(&self) -> &'static str {
+        self.heuristic.name()
+    }
+
+    fn is_admissible(&self) -> bool {
+        self.heuristic.is_admissible()
+    }
+
+    fn estimate(&self, state: &State) -> f64 {
+        if self.solved.load(Ordering::Relaxed) {
+            f64::INFINITY
+        } else {
+            self.heuristic.estimate(state)
+        }
+    }
+}
+
+fn children(state: &State) -> Vec<State> {
+    generate_sorted_moves_with_assignments(state)
+        .into_iter()
+        .map(|(from, to, amount)| move_liquid_state(state, from, to, amount))
+        .filter(|child| !is_dead_state(child))
+        .collect()
+}
+
+/// IDA* sharing the subtrees two moves below the root between `threads`
+/// workers.
+pub(crate) fn parallel_ida_star_search(
+    initial_state: State,
+    heuristic: &dyn Heuristic,
+    threads: usize,
+) -> Option<State> {
+    if is_final_state_game(&initial_state) {
+        return Some(initial_state);
+    }
+
+    let mut subtrees: Vec<Vec<State>> = Vec::new();
+    let mut seen = HashSet::from([initial_state.clone()]);
+    for child in children(&initial_state) {
+        if is_final_state_game(&child) {
+            return Some(child);
+        }
+        for grandchild in children(&child) {
+            if seen.insert(grandchild.clone()) {
+                subtrees.push(vec![initial_state.clone(), child.clone(), grandchild]);
+            }
+        }
+    }
+
+    let mut bound = heuristic.estimate(&initial_state);
+    let max_iterations = 100;
+
+    for _ in 0..max_iterations {
+        let next_subtree = AtomicUsize::new(0);
+        let solved = AtomicBool::new(false);
+        let solution: Mutex<Option<State>> = Mutex::new(None);
+        let next_bound = Mutex::new(f64::INFINITY);
+        let heuristic = UntilSolved {
+            heuristic,
+            solved: &solved,
+        };
+
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    while !solved.load(Ordering::Relaxed) {
+                        let index = next_subtree.fetch_add(1, Ordering::Relaxed);
+                        let Some(subtree) = subtrees.get(index) else {
+                            break;
+                        };
+
+                        let mut path = subtree.clone();
+                        let mut visited: HashSet<State> = subtree.iter().cloned().collect();
+                        let mut transposition_table = HashMap::new();
+
+                        let result = ida_star_search_recursive(
+                            &mut path,
+                            &mut visited,
+                            2.0,
+                            bound,
+                            &mut transposition_table,
+                            &heuristic,
+                            &mut 0,
+                        );
+
+                        if result.is_goal {
+                            solved.store(true, Ordering::Relaxed);
+                            *solution.lock().unwrap() = path.pop();
+                            break;
+                        }
+
+                        let mut next_bound = next_bound.lock().unwrap();
+                        *next_bound = next_bound.min(result.cost);
+                    }
+                });
+            }
+        });
+
+        if let Some(solution) = solution.into_inner().unwrap() {
+            return Some(solution);
+        }
+
+        let next_bound = next_bound.into_inner().unwrap();
+        if next_bound == f64::INFINITY {
+            return None;
+        }
+        bound = next_bound;
+    }
+
+    None
+}
+
+/// Native entry point for the board a `SolveRequest` describes; its `id` and
+/// `strategy` are not used. `threads` is capped at the available cores, and 0
+/// uses all of them.
+pub fn solve_parallel(request: &SolveRequest, threads: usize) -> Result<Vec<Movement>, String> {
+    let initial_state = request.initial_state()?;
+
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+    let threads = if threads == 0 {
+        cores
+    } else {
+        threads.min(cores)
+    };
+
+    // `solve_state` also settles solutions over the move limit.
+    let max_moves = initial_state.rules().max_moves;
+    match parallel_ida_star_search(initial_state.clone(), &Greedy, threads)
+        .map(|solution| shorten_movements(&initial_state, extract_movements(solution)))
+        .filter(|movements| max_moves.is_none_or(|max_moves| movements.len() <= max_moves))
+    {
+        Some(movements) => Ok(movements),
+        None => Ok(solve_state(&initial_state)?.movements),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristics::LowerBound;
+    use crate::optimizer::verify_movements;
+    use crate::test_support::{exact_distances, random_boards};
+    use crate::{Lock, Pruning, Rules};
+
+    #[test]
+    fn solves_whatever_is_solvable() {
+        for start in random_boards(41, 30, &Rules::default()) {
+            let solvable = exact_distances(&start).contains_key(&start.state);
+            let solution = parallel_ida_star_search(start.clone(), &LowerBound, 4);
+            assert_eq!(solution.is_some(), solvable, "{:?}", start.state);
+
+            if let Some(solution) = solution {
+                assert!(verify_movements(&start, &extract_movements(solution)));
+            }
+        }
+    }
+
+    #[test]
+    fn solve_parallel_keeps_the_request_rules_and_locks() {
+        let request = SolveRequest {
+            id: 0,
+            levels: 2,
+            n_of_bottles: 4,
+            bottles: vec![vec![2, 1], vec![1, 2], vec![3, 3], vec![0, 0]],
+            capacities: None,
+            locks: Some(vec![Lock::Moves(1), Lock::Moves(1), Lock::Open, Lock::Open]),
+            rules: Rules {
+                max_moves: Some(3),
+                ..Rules::default()
+            },
+            pruning: Pruning::default(),
+            strategy: None,
+        };
+        let start = request.initial_state().unwrap();
+
+        let movements = solve_parallel(&request, 64).unwrap();
+        assert!(movements.len() <= 3);
+        assert!(verify_movements(&start, &movements));
+    }
+}
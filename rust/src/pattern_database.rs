@@ -0,0 +1,665 @@
+use crate::heuristics::{Heuristic, LowerBound};
+use crate::{GoalMode, PourRule, State};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"BSPD";
+const VERSION: u8 = 1;
+
+/// Slots per key on each level of the perfect hash; more means fewer levels.
+const GAMMA: usize = 2;
+
+/// Bottles of symbols: 0 never appears, `1 + i` is `colors[i]` and
+/// `colors.len() + 1` stands in for every other color.
+type Board = Vec<Vec<u8>>;
+
+/// A board packed a fixed number of bits per cell, `levels` cells per bottle.
+type Key = Box<[u64]>;
+
+/// Goal distances of boards where only `colors` keep their identity. Abstract
+/// pours of those colors follow the pour rule while the others may move any
+/// amount, so every real move maps onto one and the distances are lower bounds.
+#[derive(Clone, Debug)]
+pub(crate) struct PatternDatabase {
+    levels: usize,
+    n_of_bottles: usize,
+    pour: PourRule,
+    colors: Vec<u8>,
+    units: Vec<(u8, usize)>,
+    complete_depth: u8,
+    exhaustive: bool,
+    index: PerfectHash,
+    /// Indexed by the board's rank in `index`.
+    distances: Vec<u8>,
+}
+
+impl PatternDatabase {
+    /// Backward breadth-first search from every abstract goal. Boards missing
+    /// from a truncated database are deeper than `complete_depth`.
+    pub(crate) fn generate(template: &State, colors: &[u8], max_entries: usize) -> Self {
+        let units = abstract_units(template, colors);
+        let pour = template.rules().pour;
+        let other = colors.len() as u8 + 1;
+        let bits = symbol_bits(other);
+        let mut distances: HashMap<Key, u8> = HashMap::new();
+
+        let mut layer = Vec::new();
+        for goal in goal_boards(&units, template.n_of_bottles, template.levels) {
+            let key = pack(&goal, template.levels, bits);
+            if distances.insert(key.clone(), 0).is_none() {
+                layer.push(key);
+            }
+        }
+
+        let mut depth: u8 = 0;
+        let mut exhaustive = true;
+        let mut board = Board::new();
+        let mut scratch = Board::new();
+        let mut packed = Vec::new();
+
+        'search: while !layer.is_empty() {
+            if depth == u8::MAX - 1 {
+                exhaustive = false;
+                break;
+            }
+
+            let mut next_layer = Vec::new();
+            for key in &layer {
+                unpack(
+                    key,
+                    template.n_of_bottles,
+                    template.levels,
+                    bits,
+                    &mut board,
+                );
+                let mut full = false;
+                for_each_predecessor(
+                    &board,
+                    template.levels,
+                    pour,
+                    other,
+                    &mut scratch,
+                    |predecessor| {
+                        if distances.len() >= max_entries {
+                            full = true;
+                            return;
+                        }
+                        pack_into(predecessor, template.levels, bits, &mut packed);
+                        if !distances.contains_key(packed.as_slice()) {
+                            let key: Key = packed.as_slice().into();
+                            distances.insert(key.clone(), depth + 1);
+                            next_layer.push(key);
+                        }
+                    },
+                );
+                if full {
+                    exhaustive = false;
+                    break 'search;
+                }
+            }
+            layer = next_layer;
+            depth += 1;
+        }
+
+        let entries: Vec<(Key, u8)> = distances.into_iter().collect();
+        let index = PerfectHash::build(entries.iter().map(|(key, _)| key.as_ref()).collect());
+        let mut table = vec![0; entries.len()];
+        for (key, distance) in &entries {
+            table[index.rank(key).expect("every key is ranked")] = *distance;
+        }
+
+        Self {
+            levels: template.levels,
+            n_of_bottles: template.n_of_bottles,
+            pour,
+            colors: colors.to_vec(),
+            units,
+            complete_depth: depth,
+            exhaustive,
+            index,
+            distances: table,
+        }
+    }
+
+    /// Little-endian binary layout, read back by `from_bytes`.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(self.levels as u8);
+        bytes.push(self.n_of_bottles as u8);
+        bytes.push(match self.pour {
+            PourRule::Full => 0,
+            PourRule::Single => 1,
+            PourRule::Partial => 2,
+        });
+        bytes.push(self.complete_depth);
+        bytes.push(self.exhaustive as u8);
+
+        bytes.push(self.colors.len() as u8);
+        bytes.extend(&self.colors);
+        bytes.push(self.units.len() as u8);
+        for &(symbol, count) in &self.units {
+            bytes.push(symbol);
+            bytes.extend((count as u32).to_le_bytes());
+        }
+
+        bytes.push(self.index.levels.len() as u8);
+        for level in &self.index.levels {
+            bytes.extend((level.len() as u32).to_le_bytes());
+            for word in level {
+                bytes.extend(word.to_le_bytes());
+            }
+        }
+        bytes.extend((self.distances.len() as u32).to_le_bytes());
+        bytes.extend(&self.distances);
+        bytes
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err("Not a pattern database".to_string());
+        }
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(format!("Unsupported pattern database version {}", version));
+        }
+
+        let levels = reader.u8()? as usize;
+        let n_of_bottles = reader.u8()? as usize;
+        let pour = match reader.u8()? {
+            0 => PourRule::Full,
+            1 => PourRule::Single,
+            2 => PourRule::Partial,
+            other => return Err(format!("Unknown pour rule {}", other)),
+        };
+        let complete_depth = reader.u8()?;
+        let exhaustive = reader.u8()? != 0;
+
+        let n_of_colors = reader.u8()? as usize;
+        let colors = reader.take(n_of_colors)?.to_vec();
+        let mut units = Vec::new();
+        for _ in 0..reader.u8()? {
+            units.push((reader.u8()?, reader.u32()? as usize));
+        }
+
+        let mut hash_levels = Vec::new();
+        for _ in 0..reader.u8()? {
+            let words = reader.u32()? as usize;
+            let level = (0..words)
+                .map(|_| reader.u64())
+                .collect::<Result<Vec<u64>, String>>()?;
+            if level.is_empty() {
+                return Err("Empty pattern database level".to_string());
+            }
+            hash_levels.push(level);
+        }
+        let index = PerfectHash::from_levels(hash_levels);
+
+        let n_of_distances = reader.u32()? as usize;
+        let distances = reader.take(n_of_distances)?.to_vec();
+        if !reader.bytes.is_empty() {
+            return Err("Trailing bytes after pattern database".to_string());
+        }
+        if distances.len() != index.len() {
+            return Err(format!(
+                "Pattern database has {} distances for {} boards",
+                distances.len(),
+                index.len()
+            ));
+        }
+
+        Ok(Self {
+            levels,
+            n_of_bottles,
+            pour,
+            colors,
+            units,
+            complete_depth,
+            exhaustive,
+            index,
+            distances,
+        })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.distances.len()
+    }
+
+    /// Mixed-color goal layouts need not be reachable from the abstract goals,
+    /// so the database only answers for plain goals.
+    fn matches(&self, state: &State) -> bool {
+        state.levels == self.levels
+            && state.has_uniform_capacity()
+            && state.n_of_bottles == self.n_of_bottles
+            && state.rules().pour == self.pour
+            && !matches!(state.rules().goal, GoalMode::Layout(_))
+            && abstract_units(state, &self.colors) == self.units
+    }
+
+    /// `None` when the database was built for a different board.
+    pub(crate) fn lookup(&self, state: &State) -> Option<f64> {
+        if !self.matches(state) {
+            return None;
+        }
+
+        // A board outside the database may still be ranked, but every stored
+        // distance is at most the one it would otherwise fall back to.
+        let board = abstract_board(state, &self.colors);
+        let key = pack(
+            &board,
+            self.levels,
+            symbol_bits(self.colors.len() as u8 + 1),
+        );
+        match self.index.rank(&key) {
+            Some(rank) => Some(self.distances[rank] as f64),
+            None if self.exhaustive => Some(f64::INFINITY),
+            None => Some((self.complete_depth + 1) as f64),
+        }
+    }
+}
+
+/// The largest of `LowerBound` and every matching database.
+pub(crate) struct PatternDatabaseHeuristic {
+    databases: Vec<Arc<PatternDatabase>>,
+}
+
+impl PatternDatabaseHeuristic {
+    pub(crate) fn new(databases: Vec<Arc<PatternDatabase>>) -> Self {
+        Self { databases }
+    }
+}
+
+impl Heuristic for PatternDatabaseHeuristic {
+    fn name(&self) -> &'static str {
+        "pattern-database"
+    }
+
+    fn is_admissible(&self) -> bool {
+        true
+    }
+
+    fn estimate(&self, state: &State) -> f64 {
+        self.databases
+            .iter()
+            .filter_map(|database| database.lookup(state))
+            .fold(LowerBound.estimate(state), f64::max)
+    }
+}
+
+/// Minimal perfect hash in the style of BBHash. Each level marks the slots
+/// that exactly one of the keys left over from the levels before hashed to,
+/// and a key's rank counts the marked slots ahead of its own.
+#[derive(Clone, Debug, Default)]
+struct PerfectHash {
+    levels: Vec<Vec<u64>>,
+    /// Marked slots before each word, earlier levels included.
+    ranks: Vec<Vec<usize>>,
+}
+
+impl PerfectHash {
+    fn build(mut keys: Vec<&[u64]>) -> Self {
+        let mut levels = Vec::new();
+
+        while !keys.is_empty() {
+            let seed = levels.len() as u64;
+            let words = keys.len() * GAMMA / 64 + 1;
+            let mut marked = vec![0u64; words];
+            let mut collided = vec![0u64; words];
+
+            for key in &keys {
+                let (word, bit) = slot(key, seed, words);
+                if marked[word] & bit != 0 {
+                    collided[word] |= bit;
+                }
+                marked[word] |= bit;
+            }
+            for (marked, collided) in marked.iter_mut().zip(&collided) {
+                *marked &= !collided;
+            }
+            keys.retain(|key| {
+                let (word, bit) = slot(key, seed, words);
+                collided[word] & bit != 0
+            });
+            levels.push(marked);
+        }
+
+        Self::from_levels(levels)
+    }
+
+    fn from_levels(levels: Vec<Vec<u64>>) -> Self {
+        let mut total = 0;
+        let ranks = levels
+            .iter()
+            .map(|level| {
+                level
+                    .iter()
+                    .map(|word| {
+                        let rank = total;
+                        total += word.count_ones() as usize;
+                        rank
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { levels, ranks }
+    }
+
+    fn len(&self) -> usize {
+        self.levels
+            .iter()
+            .flatten()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// `None` only for keys outside the set; those may also get a rank.
+    fn rank(&self, key: &[u64]) -> Option<usize> {
+        for (seed, (level, ranks)) in self.levels.iter().zip(&self.ranks).enumerate() {
+            let (word, bit) = slot(key, seed as u64, level.len());
+            if level[word] & bit != 0 {
+                return Some(ranks[word] + (level[word] & (bit - 1)).count_ones() as usize);
+            }
+        }
+        None
+    }
+}
+
+/// The word and bit a key lands on in a level of `words` words.
+fn slot(key: &[u64], seed: u64, words: usize) -> (usize, u64) {
+    let hash = key.iter().fold(mix(seed), |hash, &word| mix(hash ^ word));
+    let slot = (hash % (words as u64 * 64)) as usize;
+    (slot / 64, 1 << (slot % 64))
+}
+
+/// The splitmix64 finalizer. Unlike `std`'s hashers it is the same on every
+/// platform, so a database built natively ranks boards the same way in wasm.
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < n {
+            return Err("Truncated pattern database".to_string());
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+/// Bits per cell: enough for `other`, rounded to a power of two so that no
+/// cell straddles two words.
+fn symbol_bits(other: u8) -> usize {
+    ((u8::BITS - other.leading_zeros()) as usize).next_power_of_two()
+}
+
+fn pack(board: &Board, levels: usize, bits: usize) -> Key {
+    let mut key = Vec::new();
+    pack_into(board, levels, bits, &mut key);
+    key.into()
+}
+
+fn pack_into(board: &Board, levels: usize, bits: usize, key: &mut Vec<u64>) {
+    key.clear();
+    key.resize((board.len() * levels * bits).div_ceil(64), 0);
+    for (i, bottle) in board.iter().enumerate() {
+        for (j, &symbol) in bottle.iter().enumerate() {
+            let at = (i * levels + j) * bits;
+            key[at / 64] |= (symbol as u64) << (at % 64);
+        }
+    }
+}
+
+fn unpack(key: &[u64], n_of_bottles: usize, levels: usize, bits: usize, board: &mut Board) {
+    let mask = (1u64 << bits) - 1;
+    board.resize(n_of_bottles, Vec::new());
+    for (i, bottle) in board.iter_mut().enumerate() {
+        bottle.clear();
+        for j in 0..levels {
+            let at = (i * levels + j) * bits;
+            let symbol = (key[at / 64] >> (at % 64) & mask) as u8;
+            if symbol == 0 {
+                break;
+            }
+            bottle.push(symbol);
+        }
+    }
+}
+
+fn abstract_color(color: u8, colors: &[u8]) -> u8 {
+    match colors.iter().position(|&c| c == color) {
+        Some(i) => i as u8 + 1,
+        None => colors.len() as u8 + 1,
+    }
+}
+
+fn abstract_board(state: &State, colors: &[u8]) -> Board {
+    let mut board: Board = state.state[0..state.n_of_bottles]
+        .iter()
+        .map(|bottle| {
+            bottle.state[0..bottle.liquid_level]
+                .iter()
+                .map(|&color| abstract_color(color, colors))
+                .collect()
+        })
+        .collect();
+    board.sort();
+    board
+}
+
+fn abstract_units(state: &State, colors: &[u8]) -> Vec<(u8, usize)> {
+    let mut units: HashMap<u8, usize> = HashMap::new();
+    for bottle in &state.state[0..state.n_of_bottles] {
+        for &color in &bottle.state[0..bottle.liquid_level] {
+            *units.entry(abstract_color(color, colors)).or_default() += 1;
+        }
+    }
+    let mut units: Vec<(u8, usize)> = units.into_iter().collect();
+    units.sort();
+    units
+}
+
+/// Every solved board for the unit counts, colors split over any number of
+/// single-colored bottles.
+fn goal_boards(units: &[(u8, usize)], n_of_bottles: usize, levels: usize) -> Vec<Board> {
+    fn partitions(
+        remaining: usize,
+        max_part: usize,
+        current: &mut Vec<usize>,
+        out: &mut Vec<Vec<usize>>,
+    ) {
+        if remaining == 0 {
+            out.push(current.clone());
+            return;
+        }
+        for part in (1..=max_part.min(remaining)).rev() {
+            current.push(part);
+            partitions(remaining - part, part, current, out);
+            current.pop();
+        }
+    }
+
+    fn combine(
+        units: &[(u8, usize)],
+        levels: usize,
+        n_of_bottles: usize,
+        board: &mut Board,
+        out: &mut Vec<Board>,
+    ) {
+        let Some(&(color, count)) = units.first() else {
+            let mut goal = board.clone();
+            goal.resize(n_of_bottles, Vec::new());
+            goal.sort();
+            out.push(goal);
+            return;
+        };
+
+        let mut splits = Vec::new();
+        partitions(count, levels, &mut Vec::new(), &mut splits);
+        for split in splits {
+            if board.len() + split.len() > n_of_bottles {
+                continue;
+            }
+            for &part in &split {
+                board.push(vec![color; part]);
+            }
+            combine(&units[1..], levels, n_of_bottles, board, out);
+            board.truncate(board.len() - split.len());
+        }
+    }
+
+    let mut goals = Vec::new();
+    combine(units, levels, n_of_bottles, &mut Vec::new(), &mut goals);
+    goals
+}
+
+/// Calls `visit` with every board one abstract pour away from `board`, built
+/// in `scratch`. A pour of a database color under `PourRule::Full` moved the
+/// whole run unless it filled the target.
+fn for_each_predecessor(
+    board: &Board,
+    levels: usize,
+    pour: PourRule,
+    other: u8,
+    scratch: &mut Board,
+    mut visit: impl FnMut(&Board),
+) {
+    for to in 0..board.len() {
+        let Some(&symbol) = board[to].last() else {
+            continue;
+        };
+        let run = board[to].iter().rev().take_while(|&&s| s == symbol).count();
+
+        for amount in 1..=run {
+            if pour == PourRule::Single && amount > 1 {
+                break;
+            }
+            let remaining = board[to].len() - amount;
+            // Before the pour the target's top had to match, or it was empty.
+            if amount == run && remaining > 0 {
+                continue;
+            }
+
+            for from in 0..board.len() {
+                if from == to || board[from].len() + amount > levels {
+                    continue;
+                }
+                if pour == PourRule::Full
+                    && symbol != other
+                    && board[from].last() == Some(&symbol)
+                    && board[to].len() < levels
+                {
+                    continue;
+                }
+
+                scratch.clone_from(board);
+                scratch[to].truncate(remaining);
+                scratch[from].extend(std::iter::repeat_n(symbol, amount));
+                scratch.sort_unstable();
+                visit(scratch);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcts::Rng;
+    use crate::test_support::{board, exact_distances, random_boards, solved_boards};
+    use crate::{ida_star_search, Rules};
+
+    #[test]
+    fn lookup_never_overestimates() {
+        for (start, distances) in solved_boards(13, 15, &Rules::default()) {
+            for (colors, max_entries) in [(vec![1], 100000), (vec![1, 2], 50)] {
+                let database = PatternDatabase::generate(&start, &colors, max_entries);
+                for (bottles, &distance) in &distances {
+                    let mut state = start.clone();
+                    state.state = bottles.clone();
+                    let estimate = database.lookup(&state).unwrap();
+                    assert!(
+                        estimate <= distance as f64,
+                        "{} > {} for {:?}",
+                        estimate,
+                        distance,
+                        state.state
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn binary_round_trip_keeps_the_distances() {
+        let start = &random_boards(17, 1, &Rules::default())[0];
+        let database = PatternDatabase::generate(start, &[1], 100000);
+        let bytes = database.to_bytes();
+        let restored = PatternDatabase::from_bytes(&bytes).unwrap();
+
+        assert_eq!(restored.len(), database.len());
+        for (bottles, _) in exact_distances(start) {
+            let mut state = start.clone();
+            state.state = bottles;
+            assert_eq!(restored.lookup(&state), database.lookup(&state));
+        }
+        assert_eq!(
+            PatternDatabase::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+            "Truncated pattern database"
+        );
+    }
+
+    #[test]
+    fn optimal_search_expands_fewer_nodes_than_with_the_lower_bound() {
+        let mut rng = Rng::new(3);
+        let starts: Vec<State> = (0..40)
+            .map(|_| {
+                let mut units: Vec<u8> = (1..=4).flat_map(|color| [color; 3]).collect();
+                for i in (1..units.len()).rev() {
+                    units.swap(i, (rng.next_u64() % (i as u64 + 1)) as usize);
+                }
+                let mut bottles: Vec<&[u8]> = units.chunks(3).collect();
+                bottles.extend([[0; 3].as_slice(); 2]);
+                board(3, &bottles, Rules::default())
+            })
+            .collect();
+        let heuristic = PatternDatabaseHeuristic::new(vec![
+            Arc::new(PatternDatabase::generate(&starts[0], &[1, 2], 100000)),
+            Arc::new(PatternDatabase::generate(&starts[0], &[3, 4], 100000)),
+        ]);
+
+        let (mut with_bound, mut with_databases) = (0, 0);
+        for start in &starts {
+            ida_star_search(start.clone(), &LowerBound, &mut with_bound).unwrap();
+            ida_star_search(start.clone(), &heuristic, &mut with_databases).unwrap();
+        }
+
+        assert!(
+            with_databases < with_bound,
+            "{} expansions with the databases, {} without",
+            with_databases,
+            with_bound
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use crate::hidden::plan_hidden_move;
+use crate::{
+    convert_to_js_state, convert_to_observed_state, is_final_state_game, legal_pour_amounts,
+    move_liquid_state, release_locks, solve_state, JsGameState, Lock, Movement, State,
+    HIDDEN_PLAN_SAMPLES, UNKNOWN_COLOR,
+};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct GameSession {
+    state: State,
+    moves: Vec<Movement>,
+}
+
+impl GameSession {
+    fn try_pour(&mut self, from: usize, to: usize, amount: Option<usize>) -> Result<usize, String> {
+        let n_of_bottles = self.state.n_of_bottles;
+        if from >= n_of_bottles || to >= n_of_bottles || from == to {
+            return Err(format!("Invalid pour from {} into {}", from, to));
+        }
+        if self.remaining_moves() == Some(0) {
+            return Err("No moves left".to_string());
+        }
+
+        let amount = match amount {
+            Some(amount)
+                if !legal_pour_amounts(&self.state, from, to).any(|legal| legal == amount) =>
+            {
+                return Err(format!(
+                    "Cannot pour {} from bottle {} into bottle {}",
+                    amount, from, to
+                ));
+            }
+            Some(amount) => amount,
+            None => legal_pour_amounts(&self.state, from, to)
+                .next()
+                .unwrap_or(0),
+        };
+        if amount == 0 {
+            return Err(format!(
+                "Cannot pour from bottle {} into bottle {}",
+                from, to
+            ));
+        }
+
+        self.state = move_liquid_state(&self.state, from, to, amount);
+        self.state.previous_state = None;
+        self.moves.push(Movement { from, to, amount });
+        Ok(amount)
+    }
+
+    fn try_reveal(&mut self, bottle: usize, color: u8) -> Result<(), String> {
+        if color == 0 || color == UNKNOWN_COLOR {
+            return Err(format!("Invalid color: {}", color));
+        }
+        let target = self
+            .state
+            .state
+            .get_mut(bottle)
+            .filter(|target| target.top_liquid == UNKNOWN_COLOR && target.liquid_level > 0)
+            .ok_or_else(|| format!("Bottle {} has no hidden cell on top", bottle))?;
+
+        target.state[target.liquid_level - 1] = color;
+        target.top_liquid = color;
+        release_locks(&mut self.state);
+        Ok(())
+    }
+
+    fn try_hint(&self) -> Result<Option<Movement>, String> {
+        if is_final_state_game(&self.state) {
+            return Ok(None);
+        }
+        if self.state.has_hidden_cells() {
+            return plan_hidden_move(&self.state, HIDDEN_PLAN_SAMPLES, self.moves.len() as u64);
+        }
+
+        // The rest of the game only has the moves that are left.
+        let mut state = self.state.clone();
+        state.rules_mut().max_moves = self.remaining_moves();
+        Ok(solve_state(&state)?.movements.into_iter().next())
+    }
+}
+
+#[wasm_bindgen]
+impl GameSession {
+    /// Hidden cells hold `UNKNOWN_COLOR`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        levels: usize,
+        n_of_bottles: usize,
+        game_state_js: JsValue,
+    ) -> Result<GameSession, JsError> {
+        let game_state: JsGameState = serde_wasm_bindgen::from_value(game_state_js)
+            .map_err(|e| JsError::new(&format!("Failed to parse game state: {}", e)))?;
+        let state = convert_to_observed_state(&game_state, levels, n_of_bottles)
+            .map_err(|e| JsError::new(&e))?;
+
+        Ok(GameSession {
+            state,
+            moves: Vec::new(),
+        })
+    }
+
+    pub fn bottles(&self) -> Result<JsValue, JsError> {
+        let bottles = convert_to_js_state(&self.state).bottles;
+
+        serde_wasm_bindgen::to_value(&bottles)
+            .map_err(|e| JsError::new(&format!("Failed to serialize bottles: {}", e)))
+    }
+
+    pub fn moves(&self) -> Result<JsValue, JsError> {
+        serde_wasm_bindgen::to_value(&self.moves)
+            .map_err(|e| JsError::new(&format!("Failed to serialize moves: {}", e)))
+    }
+
+    pub fn move_count(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_solved(&self) -> bool {
+        is_final_state_game(&self.state)
+    }
+
+    pub fn remaining_moves(&self) -> Option<usize> {
+        self.state
+            .rules()
+            .max_moves
+            .map(|max_moves| max_moves.saturating_sub(self.moves.len()))
+    }
+
+    pub fn locks(&self) -> Result<JsValue, JsError> {
+        let locks: Vec<Lock> = (0..self.state.n_of_bottles)
+            .map(|i| self.state.lock(i))
+            .collect();
+
+        serde_wasm_bindgen::to_value(&locks)
+            .map_err(|e| JsError::new(&format!("Failed to serialize locks: {}", e)))
+    }
+
+    /// Bottles that take no pours until their top cell is revealed.
+    pub fn pending_reveals(&self) -> Vec<usize> {
+        (0..self.state.n_of_bottles)
+            .filter(|&i| self.state.state[i].top_liquid == UNKNOWN_COLOR)
+            .collect()
+    }
+
+    /// Without an `amount`, moves the largest one the pour rule allows.
+    pub fn pour(
+        &mut self,
+        from: usize,
+        to: usize,
+        amount: Option<usize>,
+    ) -> Result<usize, JsError> {
+        self.try_pour(from, to, amount)
+            .map_err(|e| JsError::new(&e))
+    }
+
+    pub fn reveal(&mut self, bottle: usize, color: u8) -> Result<(), JsError> {
+        self.try_reveal(bottle, color).map_err(|e| JsError::new(&e))
+    }
+
+    /// Under a move limit the hint stays within the moves left.
+    pub fn hint(&self) -> Result<JsValue, JsError> {
+        let hint = self.try_hint().map_err(|e| JsError::new(&e))?;
+
+        serde_wasm_bindgen::to_value(&hint)
+            .map_err(|e| JsError::new(&format!("Failed to serialize hint: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board, locked_board, observed_board};
+    use crate::Rules;
+
+    fn session(state: State) -> GameSession {
+        GameSession {
+            state,
+            moves: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn locked_bottles_take_no_pours_until_they_open() {
+        let mut session = session(locked_board(
+            2,
+            &[&[2, 1], &[1, 2], &[3, 3], &[0, 0]],
+            &[Lock::Moves(1), Lock::Moves(1), Lock::Open, Lock::Open],
+        ));
+
+        assert!(session.try_pour(0, 3, None).is_err());
+        assert_eq!(session.try_pour(2, 3, None), Ok(2));
+        assert!(!session.state.has_locks());
+        assert_eq!(session.try_pour(1, 2, None), Ok(1));
+    }
+
+    #[test]
+    fn reveals_only_hidden_top_cells() {
+        let mut session = session(observed_board(
+            2,
+            &[&[UNKNOWN_COLOR, 1], &[1, UNKNOWN_COLOR], &[0, 0]],
+            Rules::default(),
+        ));
+        assert_eq!(session.pending_reveals(), vec![0]);
+        assert!(session.try_pour(0, 2, None).is_err());
+
+        assert!(session.try_reveal(0, 0).is_err());
+        assert!(session.try_reveal(0, UNKNOWN_COLOR).is_err());
+        assert!(session.try_reveal(1, 2).is_err());
+        assert!(session.try_reveal(3, 2).is_err());
+        assert_eq!(session.try_reveal(0, 2), Ok(()));
+        assert!(session.pending_reveals().is_empty());
+
+        assert_eq!(session.try_pour(1, 2, None), Ok(1));
+        assert_eq!(session.pending_reveals(), vec![1]);
+    }
+
+    #[test]
+    fn counts_down_the_moves_left() {
+        let rules = Rules {
+            max_moves: Some(2),
+            ..Rules::default()
+        };
+        let mut session = session(board(2, &[&[2, 1], &[1, 2], &[0, 0]], rules));
+        assert_eq!(session.remaining_moves(), Some(2));
+
+        session.try_pour(0, 2, None).unwrap();
+        assert_eq!(session.remaining_moves(), Some(1));
+        session.try_pour(1, 0, None).unwrap();
+        assert_eq!(session.remaining_moves(), Some(0));
+        assert_eq!(
+            session.try_pour(1, 2, None),
+            Err("No moves left".to_string())
+        );
+    }
+
+    #[test]
+    fn hints_stay_within_the_moves_left() {
+        let bottles: &[&[u8]] = &[&[2, 1], &[1, 2], &[0, 0]];
+        let limited = |max_moves| {
+            session(board(
+                2,
+                bottles,
+                Rules {
+                    max_moves: Some(max_moves),
+                    ..Rules::default()
+                },
+            ))
+        };
+
+        assert_eq!(
+            limited(1).try_hint(),
+            Err("No solution within 1 moves".to_string())
+        );
+
+        let mut session = limited(2);
+        while !session.is_solved() {
+            let hint = session.try_hint().unwrap().unwrap();
+            session
+                .try_pour(hint.from, hint.to, Some(hint.amount))
+                .unwrap();
+        }
+        assert_eq!(session.move_count(), 2);
+        assert_eq!(session.try_hint(), Ok(None));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::mcts::Rng;
+use crate::{
+    convert_to_internal_state, convert_to_observed_state, is_final_state_game, legal_pour_amounts,
+    move_liquid_state, Bottles, JsGameState, Lock, PourRule, Pruning, Rules, State,
+};
+use std::collections::{HashMap, VecDeque};
+
+/// A board from top-down bottles, as `solve` takes them.
+pub(crate) fn board(levels: usize, bottles: &[&[u8]], rules: Rules) -> State {
+    convert_to_internal_state(
+        &JsGameState {
+            bottles: bottles.iter().map(|bottle| bottle.to_vec()).collect(),
+            capacities: None,
+            locks: None,
+            rules,
+            pruning: Pruning::default(),
+        },
+        levels,
+        bottles.len(),
+    )
+    .unwrap()
+}
+
+/// Like `board`, where `UNKNOWN_COLOR` cells stay hidden.
+pub(crate) fn observed_board(levels: usize, bottles: &[&[u8]], rules: Rules) -> State {
+    convert_to_observed_state(
+        &JsGameState {
+            bottles: bottles.iter().map(|bottle| bottle.to_vec()).collect(),
+            capacities: None,
+            locks: None,
+            rules,
+            pruning: Pruning::default(),
+        },
+        levels,
+        bottles.len(),
+    )
+    .unwrap()
+}
+
+/// Like `board`, with one lock per bottle.
+pub(crate) fn locked_board(levels: usize, bottles: &[&[u8]], locks: &[Lock]) -> State {
+    convert_to_internal_state(
+        &JsGameState {
+            bottles: bottles.iter().map(|bottle| bottle.to_vec()).collect(),
+            capacities: None,
+            locks: Some(locks.to_vec()),
+            rules: Rules::default(),
+            pruning: Pruning::default(),
+        },
+        levels,
+        bottles.len(),
+    )
+    .unwrap()
+}
+
+/// Shuffled boards small enough to solve exhaustively.
+pub(crate) fn random_boards(seed: u64, count: usize, rules: &Rules) -> Vec<State> {
+    let mut rng = Rng::new(seed);
+    (0..count)
+        .map(|_| {
+            let colors = 2 + (rng.next_u64() % 2) as usize;
+            let levels = 2 + (rng.next_u64() % 2) as usize;
+            let empty = 1 + (rng.next_u64() % 2) as usize;
+
+            let mut units: Vec<u8> = (1..=colors as u8)
+                .flat_map(|color| std::iter::repeat_n(color, levels))
+                .collect();
+            for i in (1..units.len()).rev() {
+                units.swap(i, (rng.next_u64() % (i as u64 + 1)) as usize);
+            }
+
+            let mut bottles: Vec<&[u8]> = units.chunks(levels).collect();
+            let empty_bottle = vec![0; levels];
+            bottles.extend(std::iter::repeat_n(empty_bottle.as_slice(), empty));
+            board(levels, &bottles, rules.clone())
+        })
+        .collect()
+}
+
+/// `random_boards` under each pour rule, `rules` otherwise.
+pub(crate) fn random_boards_per_pour_rule(seed: u64, count: usize, rules: &Rules) -> Vec<State> {
+    [PourRule::Full, PourRule::Single, PourRule::Partial]
+        .into_iter()
+        .flat_map(|pour| {
+            let rules = Rules {
+                pour,
+                ..rules.clone()
+            };
+            random_boards(seed, count, &rules)
+        })
+        .collect()
+}
+
+/// `random_boards_per_pour_rule`, each with its `exact_distances`.
+pub(crate) fn solved_boards(
+    seed: u64,
+    count: usize,
+    rules: &Rules,
+) -> Vec<(State, HashMap<Bottles, usize>)> {
+    random_boards_per_pour_rule(seed, count, rules)
+        .into_iter()
+        .map(|start| {
+            let distances = exact_distances(&start);
+            (start, distances)
+        })
+        .collect()
+}
+
+/// Exact goal distance of every solvable state reachable from `start`.
+pub(crate) fn exact_distances(start: &State) -> HashMap<Bottles, usize> {
+    let mut start = start.clone();
+    start.previous_state = None;
+
+    let mut states = vec![start.clone()];
+    let mut index: HashMap<Bottles, usize> = HashMap::from([(start.state.clone(), 0)]);
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new()];
+    let mut queue = VecDeque::from([0]);
+
+    while let Some(current) = queue.pop_front() {
+        let state = states[current].clone();
+        for from in 0..state.n_of_bottles {
+            for to in 0..state.n_of_bottles {
+                if from == to {
+                    continue;
+                }
+                for amount in legal_pour_amounts(&state, from, to) {
+                    let mut next_state = move_liquid_state(&state, from, to, amount);
+                    next_state.previous_state = None;
+                    let next = *index.entry(next_state.state.clone()).or_insert_with(|| {
+                        states.push(next_state);
+                        predecessors.push(Vec::new());
+                        queue.push_back(states.len() - 1);
+                        states.len() - 1
+                    });
+                    predecessors[next].push(current);
+                }
+            }
+        }
+    }
+
+    let mut distances = vec![None; states.len()];
+    let mut queue = VecDeque::new();
+    for (i, state) in states.iter().enumerate() {
+        if is_final_state_game(state) {
+            distances[i] = Some(0);
+            queue.push_back(i);
+        }
+    }
+    while let Some(current) = queue.pop_front() {
+        let distance = distances[current].unwrap() + 1;
+        for &predecessor in &predecessors[current] {
+            if distances[predecessor].is_none() {
+                distances[predecessor] = Some(distance);
+                queue.push_back(predecessor);
+            }
+        }
+    }
+
+    states
+        .into_iter()
+        .zip(distances)
+        .filter_map(|(state, distance)| Some((state.state, distance?)))
+        .collect()
+}
@@ -0,0 +1,234 @@
+use crate::{convert_to_observed_state, JsGameState, Pruning, Rules, State, UNKNOWN_COLOR};
+
+const HIDDEN_CELL: char = '?';
+const EMPTY_CELL: char = '.';
+const LEVELS_HEADER: &str = "levels:";
+
+fn color_from_char(cell: char) -> Option<u8> {
+    match cell {
+        'A'..='Z' => Some(cell as u8 - b'A' + 1),
+        'a'..='z' => Some(cell as u8 - b'a' + 27),
+        HIDDEN_CELL => Some(UNKNOWN_COLOR),
+        _ => None,
+    }
+}
+
+fn char_from_color(color: u8) -> Option<char> {
+    match color {
+        1..=26 => Some((b'A' + color - 1) as char),
+        27..=52 => Some((b'a' + color - 27) as char),
+        UNKNOWN_COLOR => Some(HIDDEN_CELL),
+        _ => None,
+    }
+}
+
+/// One bottle per line, bottom first: `A`-`Z` are colors 1 to 26, `a`-`z`
+/// colors 27 to 52, `?` a hidden cell and `.` an empty one. A line's length is
+/// its bottle's capacity; the longest line sets the levels unless a
+/// `levels: N` line sets more.
+pub(crate) fn parse_text(text: &str) -> Result<State, String> {
+    let mut bottles: Vec<Vec<u8>> = Vec::new();
+    let mut capacities = Vec::new();
+    let mut header_levels = None;
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(value) = line.strip_prefix(LEVELS_HEADER) {
+            if header_levels.is_some() {
+                return Err(format!("Line {}: levels given twice", line_number + 1));
+            }
+            let levels = value
+                .trim()
+                .parse::<usize>()
+                .map_err(|e| format!("Line {}: invalid levels: {}", line_number + 1, e))?;
+            header_levels = Some(levels);
+            continue;
+        }
+
+        let mut liquid = Vec::new();
+        let mut capacity = 0;
+        for cell in line.chars() {
+            capacity += 1;
+            if cell == EMPTY_CELL {
+                continue;
+            }
+
+            let color = color_from_char(cell)
+                .ok_or_else(|| format!("Line {}: invalid cell '{}'", line_number + 1, cell))?;
+            if liquid.len() + 1 < capacity {
+                return Err(format!(
+                    "Line {}: liquid above an empty cell",
+                    line_number + 1
+                ));
+            }
+            liquid.push(color);
+        }
+
+        bottles.push(liquid);
+        capacities.push(capacity);
+    }
+
+    if bottles.is_empty() {
+        return Err("No bottles in puzzle text".to_string());
+    }
+
+    let longest = capacities.iter().copied().max().unwrap_or(0);
+    let levels = match header_levels {
+        Some(levels) if levels < longest => {
+            return Err(format!(
+                "Levels {} is less than the longest bottle, {}",
+                levels, longest
+            ));
+        }
+        Some(levels) => levels,
+        None => longest,
+    };
+    let n_of_bottles = bottles.len();
+    let game_state = JsGameState {
+        bottles: bottles
+            .into_iter()
+            .map(|liquid| {
+                let mut top_down = vec![0; levels - liquid.len()];
+                top_down.extend(liquid.into_iter().rev());
+                top_down
+            })
+            .collect(),
+        capacities: capacities
+            .iter()
+            .any(|&capacity| capacity != levels)
+            .then_some(capacities),
+        locks: None,
+        rules: Rules::default(),
+        pruning: Pruning::default(),
+    };
+
+    convert_to_observed_state(&game_state, levels, n_of_bottles)
+}
+
+/// Round-trips through `parse_text`. The text holds bottles only, so states
+/// with locks, rules or pruning other than the defaults fail.
+pub(crate) fn format_text(state: &State) -> Result<String, String> {
+    if state.has_locks() {
+        return Err("Puzzle text cannot hold locks".to_string());
+    }
+    if *state.rules() != Rules::default() {
+        return Err("Puzzle text cannot hold rules other than the defaults".to_string());
+    }
+    if state.settings.pruning != Pruning::default() {
+        return Err("Puzzle text cannot hold pruning other than the defaults".to_string());
+    }
+
+    let bottles = &state.state[0..state.n_of_bottles];
+
+    let mut text = String::new();
+    if bottles
+        .iter()
+        .all(|bottle| bottle.capacity() < state.levels)
+    {
+        text.push_str(&format!("{} {}\n", LEVELS_HEADER, state.levels));
+    }
+    for bottle in bottles {
+        for &color in &bottle.state[0..bottle.liquid_level] {
+            text.push(char_from_color(color).ok_or_else(|| {
+                format!(
+                    "Color {} has no letter; puzzle text supports at most 52 colors",
+                    color
+                )
+            })?);
+        }
+        text.extend(std::iter::repeat_n(
+            EMPTY_CELL,
+            bottle.capacity() - bottle.liquid_level,
+        ));
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board, locked_board, random_boards};
+    use crate::Lock;
+    use std::sync::Arc;
+
+    fn round_trip(state: &State) -> State {
+        parse_text(&format_text(state).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn round_trips_random_boards() {
+        for state in random_boards(47, 20, &Rules::default()) {
+            let parsed = round_trip(&state);
+            assert_eq!(parsed.levels, state.levels);
+            assert_eq!(parsed, state);
+        }
+    }
+
+    #[test]
+    fn round_trips_bottles_all_shorter_than_the_levels() {
+        let game_state = JsGameState {
+            bottles: vec![vec![0, 0, 1, 2], vec![0, 0, 2, 1], vec![0, 0, 0, 0]],
+            capacities: Some(vec![2, 3, 3]),
+            locks: None,
+            rules: Rules::default(),
+            pruning: Pruning::default(),
+        };
+        let state = convert_to_observed_state(&game_state, 4, 3).unwrap();
+
+        let text = format_text(&state).unwrap();
+        assert!(text.starts_with("levels: 4\n"));
+        let parsed = parse_text(&text).unwrap();
+        assert_eq!(parsed.levels, 4);
+        assert_eq!(parsed, state);
+    }
+
+    #[test]
+    fn rejects_levels_below_the_longest_bottle() {
+        assert!(parse_text("levels: 2\nABA\n...\n").is_err());
+    }
+
+    #[test]
+    fn fails_on_colors_without_a_letter() {
+        let game_state = JsGameState {
+            bottles: vec![vec![53, 53], vec![0, 0]],
+            capacities: None,
+            locks: None,
+            rules: Rules::default(),
+            pruning: Pruning::default(),
+        };
+        let state = convert_to_observed_state(&game_state, 2, 2).unwrap();
+
+        assert!(format_text(&state).is_err());
+    }
+
+    #[test]
+    fn fails_on_what_the_text_cannot_hold() {
+        let locked = locked_board(
+            2,
+            &[&[2, 1], &[1, 2], &[0, 0]],
+            &[Lock::Open, Lock::Open, Lock::Moves(1)],
+        );
+        assert_eq!(
+            format_text(&locked).unwrap_err(),
+            "Puzzle text cannot hold locks"
+        );
+
+        let rules = Rules {
+            max_moves: Some(5),
+            ..Rules::default()
+        };
+        let challenge = board(2, &[&[2, 1], &[1, 2], &[0, 0]], rules);
+        assert!(format_text(&challenge).is_err());
+
+        let mut unpruned = board(2, &[&[2, 1], &[1, 2], &[0, 0]], Rules::default());
+        Arc::make_mut(&mut unpruned.settings)
+            .pruning
+            .skip_relabeling_pours = false;
+        assert!(format_text(&unpruned).is_err());
+    }
+}
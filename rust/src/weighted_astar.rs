@@ -0,0 +1,141 @@
+use crate::analysis::is_dead_state;
+use crate::heuristics::Heuristic;
+use crate::{is_final_state_game, move_liquid_state, pour_amounts, Position, State};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+struct OpenNode {
+    f: f64,
+    h: f64,
+    g: usize,
+    state: State,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    // `BinaryHeap` is a max-heap, so lower `f` (then lower `h`) ranks higher.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .f
+            .partial_cmp(&self.f)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.h.partial_cmp(&self.h).unwrap_or(Ordering::Equal))
+    }
+}
+
+pub(crate) enum AStarOutcome {
+    Solved(Box<State>),
+    Exhausted,
+    /// With weight 1 and an admissible heuristic, no solution is shorter
+    /// than `lower_bound`.
+    OutOfBudget {
+        lower_bound: f64,
+    },
+}
+
+/// Nodes reached again with a smaller `g` are reopened, so an admissible
+/// heuristic keeps the solution within `weight` times the optimum.
+pub(crate) fn weighted_astar_search(
+    initial_state: State,
+    heuristic: &dyn Heuristic,
+    weight: f64,
+    max_expansions: usize,
+    nodes_expanded: &mut usize,
+) -> Option<State> {
+    match astar_search(
+        initial_state,
+        heuristic,
+        weight,
+        max_expansions,
+        nodes_expanded,
+    ) {
+        AStarOutcome::Solved(solution) => Some(*solution),
+        AStarOutcome::Exhausted | AStarOutcome::OutOfBudget { .. } => None,
+    }
+}
+
+pub(crate) fn astar_search(
+    initial_state: State,
+    heuristic: &dyn Heuristic,
+    weight: f64,
+    max_expansions: usize,
+    nodes_expanded: &mut usize,
+) -> AStarOutcome {
+    let mut open = BinaryHeap::new();
+    let mut best_g: HashMap<Position, usize> = HashMap::new();
+
+    let h = heuristic.estimate(&initial_state);
+    best_g.insert(initial_state.position(), 0);
+    open.push(OpenNode {
+        f: weight * h,
+        h,
+        g: 0,
+        state: initial_state,
+    });
+
+    let mut expansions = 0;
+
+    while let Some(OpenNode { f, g, state, .. }) = open.pop() {
+        if best_g.get(&state.position()).is_some_and(|&best| best < g) {
+            continue;
+        }
+
+        if is_final_state_game(&state) {
+            return AStarOutcome::Solved(Box::new(state));
+        }
+
+        expansions += 1;
+        if expansions > max_expansions {
+            return AStarOutcome::OutOfBudget { lower_bound: f };
+        }
+        *nodes_expanded += 1;
+
+        for from in 0..state.n_of_bottles {
+            for to in 0..state.n_of_bottles {
+                if from == to {
+                    continue;
+                }
+
+                for amount in pour_amounts(&state, from, to) {
+                    let next_state = move_liquid_state(&state, from, to, amount);
+                    let next_g = g + 1;
+                    let position = next_state.position();
+                    if best_g.get(&position).is_some_and(|&best| best <= next_g) {
+                        continue;
+                    }
+                    if is_dead_state(&next_state) {
+                        continue;
+                    }
+
+                    let h = heuristic.estimate(&next_state);
+                    if h.is_infinite() {
+                        continue;
+                    }
+
+                    best_g.insert(position, next_g);
+                    open.push(OpenNode {
+                        f: next_g as f64 + weight * h,
+                        h,
+                        g: next_g,
+                        state: next_state,
+                    });
+                }
+            }
+        }
+    }
+
+    AStarOutcome::Exhausted
+}